@@ -1,92 +1,589 @@
+use crate::due_date::get_local_now;
+use crate::filter::{Query, QueryError, QueryMatch};
+use crate::history::History;
+use crate::item_input::parse_tag;
+use crate::item_input::{ItemInput, TAG_PREFIX};
+use crate::location::{TaskList, DEFAULT_LIST_NAME};
+use crate::priority::Priority;
+use crate::recurrence::spawn_next_occurrence;
+use crate::task_tree::{build_rows, set_completion, take_with_subtasks, ListRow};
+use crate::text_input::TextInput;
 use crate::todo_item::TodoItem;
 use crate::utils::StatefulList;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashSet};
 use std::fmt::{Display, Formatter, Result};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum AppStage {
     Default,
     CreateItem,
+    // Creates a subtask of the selected task
+    CreateSubItem,
     UpdateItem,
+    // Adds or removes tags of the marked tasks
+    TagItems,
     Filter,
+    SwitchList,
+    // Picks the list marked tasks are moved to
+    MoveToList,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StatusMessage {
+    // Tasks could not be saved, quitting now would lose the changes
+    SaveError(String),
+    Error(String),
+    Info(String),
 }
 
 pub struct App {
+    // Selection refers to the rows returned by `get_visible_rows`
     pub list: StatefulList<TodoItem>,
+    // Name of the open task list
+    pub list_name: String,
+    pub list_title: String,
+    // Names of the lists user can switch to
+    pub list_switcher: StatefulList<String>,
     pub stage: Arc<Mutex<AppStage>>,
-    pub item_name_input: String,
-    pub filter_term: String,
+    pub item_name_input: TextInput,
+    pub filter_term: TextInput,
+    // Text in the filter matches subsequences of names, ranked by score
+    pub is_fuzzy_filter: bool,
     pub sorting_order: AppSorting,
+    // Tasks with hidden subtasks
+    pub collapsed: HashSet<Uuid>,
+    // Tasks bulk actions apply to instead of the selected one
+    pub marked: HashSet<Uuid>,
+    // Task where the range selection started, rows from it to the selected one are marked
+    range_start: Option<Uuid>,
+    // Completing a task completes its subtasks as well
+    pub cascade_completion: bool,
+    // Details of the selected task are shown next to the list
+    pub show_details: bool,
+    // Number of rows the list shows at once, updated when it is drawn
+    pub list_height: usize,
+    // Archived tasks of the list, shown instead of it when set
+    pub archive: Option<Vec<TodoItem>>,
+    // Newer release found by the background update check
+    pub available_update: Option<String>,
+    // Shown instead of the help until the next key press
+    pub status_message: Option<StatusMessage>,
+    // Time of the latest change which is not saved yet
+    last_unsaved_change: Option<Instant>,
+    history: History,
 }
 
 impl App {
     pub fn new(items: Vec<TodoItem>) -> App {
         let mut app = App {
             list: StatefulList::new(items),
+            list_name: DEFAULT_LIST_NAME.to_string(),
+            list_title: DEFAULT_LIST_NAME.to_string(),
+            list_switcher: StatefulList::new(vec![]),
             stage: Arc::new(Mutex::new(AppStage::Default)),
-            item_name_input: String::new(),
+            item_name_input: TextInput::default(),
             sorting_order: AppSorting::ByDate(SortingOrder::Ascending),
-            filter_term: String::new(),
+            filter_term: TextInput::default(),
+            is_fuzzy_filter: false,
+            collapsed: HashSet::new(),
+            marked: HashSet::new(),
+            range_start: None,
+            cascade_completion: false,
+            show_details: false,
+            list_height: 0,
+            archive: None,
+            available_update: None,
+            status_message: None,
+            last_unsaved_change: None,
+            history: History::default(),
         };
 
-        app.sort_by_date(SortingOrder::Ascending);
+        app.set_sorting_order(app.get_sorting_for_list());
         app.select_first_task_or_none();
         app
     }
 
     pub fn add_new_item(&mut self) {
-        if self.item_name_input.is_empty() {
+        self.push_new_item(None);
+    }
+
+    pub fn add_new_subitem(&mut self) {
+        if let Some(parent) = self.get_selected_item() {
+            self.push_new_item(Some(parent.id));
+        }
+    }
+
+    fn push_new_item(&mut self, parent_id: Option<Uuid>) {
+        let input = ItemInput::parse(self.item_name_input.get_text(), get_local_now().date());
+        if input.name.is_empty() {
             return;
         }
-        self.list.items.push(TodoItem::new(&self.item_name_input));
+        self.item_name_input.add_to_history();
+        let mut item = input.into_item();
+        item.parent_id = parent_id;
+        if let Some(parent_id) = parent_id {
+            // New subtask should not end up hidden
+            self.collapsed.remove(&parent_id);
+        }
+        if self.sorting_order == AppSorting::Manual {
+            // New task goes to the end of the custom order
+            item.position = Some(self.list.items.len() as u32);
+        }
+        self.record_change(format!("add \"{}\"", item.name));
+        self.list.items.push(item);
+        self.mark_as_changed();
     }
 
     pub fn update_item(&mut self) {
-        if self.item_name_input.is_empty() {
+        let input = ItemInput::parse(self.item_name_input.get_text(), get_local_now().date());
+        if input.name.is_empty() {
             return;
         }
-        match self.list.get_selected_item() {
+        self.item_name_input.add_to_history();
+        match self.get_selected_item() {
             None => {}
             Some(selected_item) => {
-                for item in &mut self.list.items {
-                    if item.id == selected_item.id {
-                        item.set_name(self.item_name_input.as_str());
-                    }
+                self.record_change(format!("edit \"{}\"", selected_item.name));
+                if let Some(item) = self
+                    .list
+                    .items
+                    .iter_mut()
+                    .find(|item| item.id == selected_item.id)
+                {
+                    input.apply_to(item);
                 }
+                self.mark_as_changed();
             }
         }
     }
 
+    /// Completes the target tasks, or reopens them if all of them are done.
     pub fn toggle_task(&mut self) {
-        if let Some(selected_item) = self.list.get_selected_item() {
-            for item in &mut self.list.items {
-                if item.id == selected_item.id {
-                    item.set_completion(!item.completed);
-                }
+        let targets = self.get_target_items();
+        if targets.is_empty() {
+            return;
+        }
+        let is_complete = targets.iter().any(|item| !item.completed);
+        let action = if is_complete { "complete" } else { "reopen" };
+        self.record_change(format!("{} {}", action, describe_tasks(&targets)));
+
+        let today = get_local_now().date();
+        let mut next_occurrences = vec![];
+        for target in &targets {
+            // Tasks done already keep their completion date, their subtasks are still completed
+            let is_changed = self
+                .list
+                .items
+                .iter()
+                .any(|item| item.id == target.id && item.completed != is_complete);
+            set_completion(
+                &mut self.list.items,
+                target.id,
+                is_complete,
+                self.cascade_completion,
+            );
+            if !is_changed {
+                continue;
             }
+            next_occurrences.extend(spawn_next_occurrence(
+                &mut self.list.items,
+                target.id,
+                today,
+            ));
+        }
+        if let [next] = next_occurrences.as_slice() {
+            self.status_message = Some(StatusMessage::Info(format!(
+                "Next \"{}\" is due {}",
+                next.name,
+                next.get_due_string(today).unwrap_or_default()
+            )));
+        }
+        self.finish_bulk_change();
+    }
+
+    /// Replaces notes of the task, e.g. after they are edited in an external editor.
+    pub fn set_notes(&mut self, id: Uuid, notes: &str) {
+        let index = match self
+            .list
+            .items
+            .iter()
+            .position(|item| item.id == id && item.notes != notes)
+        {
+            Some(index) => index,
+            None => return,
         };
+
+        self.record_change(format!("edit notes of \"{}\"", self.list.items[index].name));
+        self.list.items[index].set_notes(notes);
+        self.mark_as_changed();
+    }
+
+    pub fn toggle_details(&mut self) {
+        self.show_details = !self.show_details;
     }
 
+    pub fn raise_priority(&mut self) {
+        self.change_priority(Priority::raise);
+    }
+
+    pub fn lower_priority(&mut self) {
+        self.change_priority(Priority::lower);
+    }
+
+    fn change_priority(&mut self, change: fn(Priority) -> Priority) {
+        let targets: Vec<TodoItem> = self
+            .get_target_items()
+            .into_iter()
+            .filter(|item| change(item.priority) != item.priority)
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+
+        self.record_change(format!("change priority of {}", describe_tasks(&targets)));
+        let ids: HashSet<Uuid> = targets.iter().map(|item| item.id).collect();
+        for item in &mut self.list.items {
+            if ids.contains(&item.id) {
+                item.set_priority(change(item.priority));
+            }
+        }
+        self.finish_bulk_change();
+    }
+
+    /// Applies the tag input to the target tasks, `#tag` adds a tag and `-#tag` removes it.
+    /// The `#` can be left out.
+    pub fn tag_tasks(&mut self) {
+        let mut added = BTreeSet::new();
+        let mut removed = BTreeSet::new();
+        for word in self.item_name_input.get_text().split_whitespace() {
+            let (tags, word) = match word.strip_prefix('-') {
+                Some(word) => (&mut removed, word),
+                None => (&mut added, word),
+            };
+            let word = word.trim_start_matches(TAG_PREFIX);
+            tags.extend(parse_tag(&format!("{}{}", TAG_PREFIX, word)));
+        }
+
+        let targets: Vec<TodoItem> = self
+            .get_target_items()
+            .into_iter()
+            .filter(|item| {
+                !added.is_subset(&item.tags) || removed.iter().any(|tag| item.tags.contains(tag))
+            })
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+
+        self.record_change(format!("tag {}", describe_tasks(&targets)));
+        let ids: HashSet<Uuid> = targets.iter().map(|item| item.id).collect();
+        for item in &mut self.list.items {
+            if ids.contains(&item.id) {
+                let tags = item.tags.union(&added).cloned().collect::<BTreeSet<_>>();
+                item.set_tags(tags.difference(&removed).cloned().collect());
+            }
+        }
+        self.finish_bulk_change();
+    }
+
+    pub fn move_task_down(&mut self) {
+        self.move_task(true);
+    }
+
+    pub fn move_task_up(&mut self) {
+        self.move_task(false);
+    }
+
+    /// Swaps the selected task with the next or previous shown task of the same parent.
+    /// The list switches to the custom order, which starts as the current one.
+    fn move_task(&mut self, is_down: bool) {
+        let rows = self.get_visible_rows();
+        let selected_row = match self.list.state.selected().and_then(|index| rows.get(index)) {
+            Some(row) => row,
+            None => return,
+        };
+        let siblings: Vec<&ListRow> = rows
+            .iter()
+            .filter(|row| {
+                row.depth == selected_row.depth && row.item.parent_id == selected_row.item.parent_id
+            })
+            .collect();
+        let sibling_index = siblings
+            .iter()
+            .position(|row| row.item.id == selected_row.item.id)
+            .unwrap_or_default();
+        let neighbour = if is_down {
+            siblings.get(sibling_index + 1)
+        } else {
+            sibling_index
+                .checked_sub(1)
+                .and_then(|index| siblings.get(index))
+        };
+        let neighbour = match neighbour {
+            Some(row) => row,
+            None => return,
+        };
+
+        self.record_change(format!("move \"{}\"", selected_row.item.name));
+        self.sorting_order = AppSorting::Manual;
+        self.list.items.swap(selected_row.index, neighbour.index);
+        for (position, item) in self.list.items.iter_mut().enumerate() {
+            item.position = Some(position as u32);
+        }
+
+        let selected_id = selected_row.item.id;
+        let selected = self
+            .get_visible_rows()
+            .iter()
+            .position(|row| row.item.id == selected_id);
+        self.list.state.select(selected);
+        self.mark_as_changed();
+    }
+
+    /// Removes the target tasks together with their subtasks.
     pub fn remove_task(&mut self) {
-        if let Some(selected_item) = self.list.get_selected_item() {
-            let filtered_items: Vec<TodoItem> = self
-                .list
-                .items
-                .iter()
-                .filter(|item| item.id != selected_item.id)
-                .cloned()
-                .collect();
-            self.list = StatefulList::new(filtered_items);
-            self.select_first_task_or_none();
+        let targets = self.get_target_items();
+        if targets.is_empty() {
+            return;
+        }
+
+        self.record_change(format!("remove {}", describe_tasks(&targets)));
+        let mut items = self.list.items.clone();
+        take_with_subtasks(&mut items, &targets.iter().map(|item| item.id).collect());
+        self.list = StatefulList::new(items);
+        self.select_first_task_or_none();
+        self.finish_bulk_change();
+    }
+
+    /// Marks the selected task, or unmarks it if it is marked already.
+    pub fn toggle_mark(&mut self) {
+        if let Some(selected_item) = self.get_selected_item() {
+            if !self.marked.remove(&selected_item.id) {
+                self.marked.insert(selected_item.id);
+            }
         }
     }
 
+    /// Starts marking the rows between this one and the selected one, or ends it keeping
+    /// them marked.
+    pub fn toggle_range_selection(&mut self) {
+        if self.range_start.is_some() {
+            self.marked = self.get_marked_ids();
+            self.range_start = None;
+        } else {
+            self.range_start = self.get_selected_item().map(|item| item.id);
+        }
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+        self.range_start = None;
+    }
+
+    /// Marked tasks shown in the list, including the range being selected.
+    pub fn get_marked_ids(&self) -> HashSet<Uuid> {
+        let rows = self.get_visible_rows();
+        let range_start = self
+            .range_start
+            .and_then(|id| rows.iter().position(|row| row.item.id == id));
+        let range = match (range_start, self.list.state.selected()) {
+            (Some(start), Some(selected)) => Some(start.min(selected)..=start.max(selected)),
+            _ => None,
+        };
+
+        rows.iter()
+            .enumerate()
+            .filter(|(index, row)| {
+                self.marked.contains(&row.item.id)
+                    || range.as_ref().is_some_and(|range| range.contains(index))
+            })
+            .map(|(_, row)| row.item.id)
+            .collect()
+    }
+
+    /// Marked tasks, or the selected one when nothing is marked.
+    pub fn get_target_ids(&self) -> HashSet<Uuid> {
+        let marked = self.get_marked_ids();
+        if !marked.is_empty() {
+            return marked;
+        }
+
+        self.get_selected_item()
+            .map(|item| item.id)
+            .into_iter()
+            .collect()
+    }
+
+    fn get_target_items(&self) -> Vec<TodoItem> {
+        let ids = self.get_target_ids();
+        self.list
+            .items
+            .iter()
+            .filter(|item| ids.contains(&item.id))
+            .cloned()
+            .collect()
+    }
+
+    // Marks are used up by the change they were made for
+    fn finish_bulk_change(&mut self) {
+        self.clear_marks();
+        self.mark_as_changed();
+    }
+
+    /// Replaces items with the ones left after moving tasks to another list.
+    /// Moved tasks cannot be brought back with undo, so the history is cleared.
+    pub fn apply_move(&mut self, items: Vec<TodoItem>, moved_count: usize, list_name: &str) {
+        self.history.clear();
+        self.clear_marks();
+        self.restore_items(items);
+        self.status_message = Some(StatusMessage::Info(format!(
+            "Moved {} task{} to {}",
+            moved_count,
+            if moved_count == 1 { "" } else { "s" },
+            list_name
+        )));
+    }
+
+    pub fn undo(&mut self) {
+        let items = self.list.items.clone();
+        self.status_message = Some(StatusMessage::Info(match self.history.undo(items) {
+            Some((description, items)) => {
+                self.restore_items(items);
+                format!("Undone: {}", description)
+            }
+            None => "Nothing to undo".to_string(),
+        }));
+    }
+
+    pub fn redo(&mut self) {
+        let items = self.list.items.clone();
+        self.status_message = Some(StatusMessage::Info(match self.history.redo(items) {
+            Some((description, items)) => {
+                self.restore_items(items);
+                format!("Redone: {}", description)
+            }
+            None => "Nothing to redo".to_string(),
+        }));
+    }
+
+    // Should be called before every change of the items, so it can be undone
+    fn record_change(&mut self, description: String) {
+        self.history.record(description, self.list.items.clone());
+    }
+
+    fn restore_items(&mut self, items: Vec<TodoItem>) {
+        self.list.items = items;
+        self.set_sorting_order(self.sorting_order.clone());
+        self.keep_selection_in_rows();
+        self.mark_as_changed();
+    }
+
+    /// Replaces items with the ones left after archiving done tasks.
+    /// Archived tasks cannot be brought back with undo, so the history is cleared.
+    pub fn apply_archive(&mut self, items: Vec<TodoItem>, archived_count: usize) {
+        self.history.clear();
+        self.restore_items(items);
+        self.status_message = Some(StatusMessage::Info(format!(
+            "Archived {} task{}",
+            archived_count,
+            if archived_count == 1 { "" } else { "s" }
+        )));
+    }
+
+    /// Shows archived tasks instead of the list, most recently done first.
+    pub fn open_archive(&mut self, mut items: Vec<TodoItem>) {
+        items.sort_by_key(|item| Reverse(item.get_completed_date()));
+        self.archive = Some(items);
+        self.select_first_task_or_none();
+    }
+
+    pub fn close_archive(&mut self) {
+        self.archive = None;
+        self.select_first_task_or_none();
+    }
+
+    // Tasks of the list or the archive, depending on what is shown
+    fn get_shown_items(&self) -> &Vec<TodoItem> {
+        self.archive.as_ref().unwrap_or(&self.list.items)
+    }
+
+    /// Returns `true` if there are unsaved changes and nothing has changed for `delay`,
+    /// so a burst of changes is saved at once.
+    pub fn has_settled_changes(&self, delay: Duration) -> bool {
+        match self.last_unsaved_change {
+            Some(changed_at) => changed_at.elapsed() >= delay,
+            None => false,
+        }
+    }
+
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.last_unsaved_change.is_some()
+    }
+
+    pub fn mark_as_saved(&mut self) {
+        self.last_unsaved_change = None;
+    }
+
+    pub fn mark_as_changed(&mut self) {
+        self.last_unsaved_change = Some(Instant::now());
+    }
+
+    /// Replaces items with the ones from another list.
+    pub fn open_list(&mut self, task_list: &TaskList, items: Vec<TodoItem>) {
+        self.list = StatefulList::new(items);
+        self.list_name = task_list.name.clone();
+        self.list_title = task_list.title.clone();
+        self.last_unsaved_change = None;
+        self.history.clear();
+        self.archive = None;
+        self.clear_marks();
+        self.set_sorting_order(self.get_sorting_for_list());
+        self.select_first_task_or_none();
+    }
+
+    pub fn open_list_switcher(&mut self, list_names: Vec<String>) {
+        let current_list_index = list_names.iter().position(|name| *name == self.list_name);
+        self.list_switcher = StatefulList::new(list_names);
+        self.list_switcher
+            .state
+            .select(current_list_index.or(Some(0)));
+        self.set_stage(AppStage::SwitchList);
+    }
+
+    /// Opens the list switcher to pick where the target tasks are moved, the open list is
+    /// not offered.
+    pub fn open_move_to_list(&mut self, mut list_names: Vec<String>) {
+        if self.get_target_ids().is_empty() {
+            return;
+        }
+        list_names.retain(|name| *name != self.list_name);
+        let selected = if list_names.is_empty() { None } else { Some(0) };
+        self.list_switcher = StatefulList::new(list_names);
+        self.list_switcher.state.select(selected);
+        self.set_stage(AppStage::MoveToList);
+    }
+
     pub fn set_stage(&mut self, stage: AppStage) {
         self.reset_item_name_input();
         match stage {
             AppStage::UpdateItem => {
-                if let Some(selected_item) = self.list.get_selected_item() {
-                    self.item_name_input = selected_item.name;
+                if let Some(selected_item) = self.get_selected_item() {
+                    self.item_name_input
+                        .set_text(&selected_item.to_input_string());
+                    *self.stage.lock().unwrap() = stage;
+                }
+            }
+            AppStage::CreateSubItem => {
+                if self.get_selected_item().is_some() {
+                    *self.stage.lock().unwrap() = stage;
+                }
+            }
+            AppStage::TagItems => {
+                if !self.get_target_ids().is_empty() {
                     *self.stage.lock().unwrap() = stage;
                 }
             }
@@ -94,11 +591,25 @@ impl App {
         }
     }
 
+    // Lists ordered by hand open in their custom order, others keep the current sorting
+    fn get_sorting_for_list(&self) -> AppSorting {
+        if self.list.items.iter().any(|item| item.position.is_some()) {
+            AppSorting::Manual
+        } else if self.sorting_order == AppSorting::Manual {
+            AppSorting::ByDate(SortingOrder::Ascending)
+        } else {
+            self.sorting_order.clone()
+        }
+    }
+
     fn set_sorting_order(&mut self, order: AppSorting) {
         self.sorting_order = order.clone();
         match order {
+            AppSorting::Manual => self.sort_by_position(),
             AppSorting::ByDate(order) => self.sort_by_date(order),
             AppSorting::ByCompletion(order) => self.sort_by_completion(order),
+            AppSorting::ByDueDate(order) => self.sort_by_due_date(order),
+            AppSorting::ByPriority(order) => self.sort_by_priority(order),
         };
     }
 
@@ -108,6 +619,11 @@ impl App {
             AppSorting::ByDate(SortingOrder::Descending),
             AppSorting::ByCompletion(SortingOrder::Ascending),
             AppSorting::ByCompletion(SortingOrder::Descending),
+            AppSorting::ByDueDate(SortingOrder::Ascending),
+            AppSorting::ByDueDate(SortingOrder::Descending),
+            AppSorting::ByPriority(SortingOrder::Ascending),
+            AppSorting::ByPriority(SortingOrder::Descending),
+            AppSorting::Manual,
         ];
 
         let current_sorting_index = sorting_rotation_list
@@ -115,50 +631,150 @@ impl App {
             .position(|sorting| sorting.eq(&self.sorting_order))
             .unwrap();
         let next_sorting = match sorting_rotation_list.get(current_sorting_index + 1) {
-            None => sorting_rotation_list.get(0).unwrap(),
+            None => sorting_rotation_list.first().unwrap(),
             Some(order) => order,
         };
 
         self.set_sorting_order(next_sorting.clone());
     }
 
-    pub fn item_input_add_character(&mut self, letter: char) {
-        self.item_name_input = format!("{}{}", self.item_name_input, letter);
+    pub fn reset_item_name_input(&mut self) {
+        self.item_name_input.clear()
     }
 
-    pub fn item_input_remove_character(&mut self) {
-        self.item_name_input.pop();
+    pub fn get_stage_clone(&self) -> AppStage {
+        *self.stage.clone().lock().unwrap()
     }
 
-    pub fn reset_item_name_input(&mut self) {
-        self.item_name_input = String::new()
+    pub fn get_filter_query(&self) -> std::result::Result<Query, QueryError> {
+        Query::parse(self.filter_term.get_text(), get_local_now().date())
+    }
+
+    /// Returns items matching the filter together with the matched name characters.
+    /// In fuzzy mode the best matches come first.
+    pub fn get_filtered_matches(&self) -> Vec<(usize, TodoItem, BTreeSet<usize>)> {
+        // Invalid query does not hide anything, the error is shown instead
+        let query = match self.get_filter_query() {
+            Ok(query) => query,
+            Err(_) => Query::And(vec![]),
+        };
+
+        let mut matches: Vec<(usize, &TodoItem, QueryMatch)> = self
+            .get_shown_items()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| {
+                let query_match = query.get_match(item, self.is_fuzzy_filter)?;
+                Some((index, item, query_match))
+            })
+            .collect();
+        if self.is_fuzzy_filter {
+            matches.sort_by_key(|(_, _, query_match)| Reverse(query_match.score));
+        }
+
+        matches
+            .into_iter()
+            .map(|(index, item, query_match)| (index, item.clone(), query_match.positions))
+            .collect()
     }
 
-    pub fn filter_term_add_character(&mut self, letter: char) {
-        self.filter_term = format!("{}{}", self.filter_term, letter);
+    /// Returns tasks matching the filter arranged into a tree, see `build_rows`.
+    pub fn get_visible_rows(&self) -> Vec<ListRow> {
+        let matches = self
+            .get_filtered_matches()
+            .into_iter()
+            .map(|(index, _, positions)| (index, positions))
+            .collect();
+
+        build_rows(self.get_shown_items(), matches, &self.collapsed)
     }
 
-    pub fn filter_term_remove_character(&mut self) {
-        self.filter_term.pop();
+    pub fn get_selected_item(&self) -> Option<TodoItem> {
+        self.get_selected_row().map(|row| row.item)
     }
 
-    pub fn get_stage_clone(&self) -> AppStage {
-        *self.stage.clone().lock().unwrap()
+    pub fn get_selected_row(&self) -> Option<ListRow> {
+        let selected = self.list.state.selected()?;
+        self.get_visible_rows().into_iter().nth(selected)
+    }
+
+    pub fn select_next(&mut self) {
+        let number_of_rows = self.get_visible_rows().len();
+        self.list.select_next(number_of_rows);
+    }
+
+    pub fn select_previous(&mut self) {
+        let number_of_rows = self.get_visible_rows().len();
+        self.list.select_previous(number_of_rows);
+    }
+
+    /// Moves the selection by `offset` rows, stopping at the first and the last one.
+    pub fn select_by(&mut self, offset: isize) {
+        let number_of_rows = self.get_visible_rows().len();
+        self.list.select_offset(offset, number_of_rows);
     }
 
-    pub fn get_filtered_items(&self) -> Vec<(usize, TodoItem)> {
-        let mut items: Vec<(usize, TodoItem)> = vec![];
-        for (index, item) in self.list.items.iter().enumerate() {
-            if item
-                .name
-                .to_lowercase()
-                .contains(&self.filter_term.to_lowercase())
-            {
-                items.push((index, item.clone()));
+    pub fn select_first(&mut self) {
+        let number_of_rows = self.get_visible_rows().len();
+        self.list
+            .state
+            .select(Some(0).filter(|_| number_of_rows > 0));
+    }
+
+    pub fn select_last(&mut self) {
+        let number_of_rows = self.get_visible_rows().len();
+        self.list.state.select(number_of_rows.checked_sub(1));
+    }
+
+    /// Rows to move by a page, at least one.
+    pub fn get_page_size(&self) -> usize {
+        self.list_height.max(1)
+    }
+
+    /// Selects the task shown with this number, numbers are positions in the unfiltered list.
+    pub fn select_task_number(&mut self, number: usize) {
+        let row = self
+            .get_visible_rows()
+            .iter()
+            .position(|row| row.index + 1 == number);
+        match row {
+            Some(row) => self.list.state.select(Some(row)),
+            None => {
+                self.status_message =
+                    Some(StatusMessage::Info(format!("No task {} is shown", number)))
             }
         }
+    }
+
+    pub fn expand_task(&mut self) {
+        if let Some(selected_item) = self.get_selected_item() {
+            self.collapsed.remove(&selected_item.id);
+        }
+    }
+
+    /// Hides subtasks of the selected task, or selects the parent if there is nothing to hide.
+    pub fn collapse_task(&mut self) {
+        let row = match self.get_selected_row() {
+            Some(row) => row,
+            None => return,
+        };
+        if row.progress.is_some() && !row.is_collapsed {
+            self.collapsed.insert(row.item.id);
+            return;
+        }
 
-        items
+        let parent_row = row.item.parent_id.and_then(|parent_id| {
+            self.get_visible_rows()
+                .iter()
+                .position(|row| row.item.id == parent_id)
+        });
+        if parent_row.is_some() {
+            self.list.state.select(parent_row);
+        }
+    }
+
+    pub fn toggle_fuzzy_filter(&mut self) {
+        self.is_fuzzy_filter = !self.is_fuzzy_filter;
     }
 
     fn sort_by_date(&mut self, sorting_order: SortingOrder) {
@@ -179,18 +795,64 @@ impl App {
             });
     }
 
+    // Tasks without due date are always last
+    fn sort_by_due_date(&mut self, sorting_order: SortingOrder) {
+        self.list.items.sort_by(|item_a, item_b| {
+            let deadline_a = item_a.due.map(|due| due.get_deadline());
+            let deadline_b = item_b.due.map(|due| due.get_deadline());
+
+            match (deadline_a, deadline_b) {
+                (Some(a), Some(b)) => match sorting_order {
+                    SortingOrder::Ascending => a.cmp(&b),
+                    SortingOrder::Descending => a.cmp(&b).reverse(),
+                },
+                (a, b) => a.is_none().cmp(&b.is_none()),
+            }
+        });
+    }
+
+    // Tasks which were never moved come last, in the order they are stored
+    fn sort_by_position(&mut self) {
+        self.list
+            .items
+            .sort_by_key(|item| item.position.unwrap_or(u32::MAX));
+    }
+
+    // Sorting is stable, tasks of the same priority are ordered by date
+    fn sort_by_priority(&mut self, sorting_order: SortingOrder) {
+        self.sort_by_date(SortingOrder::Ascending);
+        self.list
+            .items
+            .sort_by(|item_a, item_b| match sorting_order {
+                SortingOrder::Ascending => item_a.priority.cmp(&item_b.priority).reverse(),
+                SortingOrder::Descending => item_a.priority.cmp(&item_b.priority),
+            });
+    }
+
     fn select_first_task_or_none(&mut self) {
-        if !self.list.items.is_empty() {
+        if !self.get_shown_items().is_empty() {
             self.list.state.select(Some(0));
         } else {
             self.list.state.select(None);
         }
     }
+
+    // Selection stays in place unless its row is gone
+    fn keep_selection_in_rows(&mut self) {
+        let number_of_rows = self.get_visible_rows().len();
+        match self.list.state.selected() {
+            Some(selected) if selected < number_of_rows => {}
+            _ => self.list.state.select(number_of_rows.checked_sub(1)),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::{TimeZone, Utc};
+    use std::path::Path;
+    use termion::event::Key;
 
     static TASK_A_NAME: &str = "A";
     static TASK_B_NAME: &str = "B";
@@ -207,8 +869,8 @@ mod tests {
             AppSorting::ByDate(SortingOrder::Ascending)
         );
         assert_eq!(*app.stage.lock().unwrap(), AppStage::Default);
-        assert_eq!(app.item_name_input, "");
-        assert_eq!(app.filter_term, "");
+        assert_eq!(app.item_name_input.get_text(), "");
+        assert_eq!(app.filter_term.get_text(), "");
 
         // Correct item is selected
         assert_eq!(app.list.get_selected_item().unwrap().id, items[0].id);
@@ -217,9 +879,9 @@ mod tests {
     #[test]
     fn it_add_new_item() {
         let mut app = App::new(vec![]);
-        app.item_input_add_character('a');
+        app.item_name_input.handle_key(Key::Char('a'));
 
-        assert_eq!(app.item_name_input, "a");
+        assert_eq!(app.item_name_input.get_text(), "a");
 
         app.add_new_item();
         assert_eq!(app.list.items[0].name, "a");
@@ -228,9 +890,9 @@ mod tests {
     #[test]
     fn it_should_edit_existing_item() {
         let mut app = App::new(vec![TodoItem::new(TASK_A_NAME)]);
-        app.item_input_add_character('a');
+        app.item_name_input.handle_key(Key::Char('a'));
 
-        assert_eq!(app.item_name_input, "a");
+        assert_eq!(app.item_name_input.get_text(), "a");
 
         app.update_item();
         assert_eq!(app.list.items.len(), 1);
@@ -254,6 +916,42 @@ mod tests {
         assert_eq!(app.list.items.len(), 0);
     }
 
+    #[test]
+    fn it_tracks_unsaved_changes() {
+        let mut app = App::new(create_todo_items());
+        assert!(!app.has_unsaved_changes());
+
+        app.toggle_task();
+        assert!(app.has_unsaved_changes());
+        assert!(app.has_settled_changes(Duration::from_millis(0)));
+        assert!(!app.has_settled_changes(Duration::from_secs(60)));
+
+        app.mark_as_saved();
+        assert!(!app.has_unsaved_changes());
+
+        // Nothing is changed when input is empty
+        app.add_new_item();
+        assert!(!app.has_unsaved_changes());
+    }
+
+    #[test]
+    fn it_opens_another_list() {
+        let mut app = App::new(create_todo_items());
+        app.toggle_task();
+
+        app.open_list_switcher(vec!["home".to_string(), "todos".to_string()]);
+        assert_eq!(app.get_stage_clone(), AppStage::SwitchList);
+        assert_eq!(app.list_switcher.get_selected_item().unwrap(), "todos");
+
+        let task_list = TaskList::in_dir(Path::new("/data"), "home");
+        app.open_list(&task_list, vec![TodoItem::new(TASK_B_NAME)]);
+        assert_eq!(app.list_name, "home");
+        assert_eq!(app.list_title, "home");
+        assert_eq!(app.list.items.len(), 1);
+        assert_eq!(app.list.get_selected_item().unwrap().name, TASK_B_NAME);
+        assert!(!app.has_unsaved_changes());
+    }
+
     #[test]
     fn it_toggles_sorting() {
         let items = create_todo_items();
@@ -282,6 +980,23 @@ mod tests {
 
         assert_eq!(app.list.items[1].id, item_id);
 
+        app.toggle_sorting();
+        assert_eq!(
+            app.sorting_order,
+            AppSorting::ByDueDate(SortingOrder::Ascending)
+        );
+
+        app.toggle_sorting();
+        app.toggle_sorting();
+        assert_eq!(
+            app.sorting_order,
+            AppSorting::ByPriority(SortingOrder::Ascending)
+        );
+
+        app.toggle_sorting();
+        app.toggle_sorting();
+        assert_eq!(app.sorting_order, AppSorting::Manual);
+
         app.toggle_sorting();
         assert_eq!(
             app.sorting_order,
@@ -289,15 +1004,129 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_moves_tasks_in_custom_order() {
+        let mut items = vec![TodoItem::new("A"), TodoItem::new("B"), TodoItem::new("C")];
+        let mut subtask = TodoItem::new("A1");
+        subtask.parent_id = Some(items[0].id);
+        items.push(subtask);
+        let mut app = App::new(items);
+        let get_names = |app: &App| -> Vec<String> {
+            app.get_visible_rows()
+                .into_iter()
+                .map(|row| row.item.name)
+                .collect()
+        };
+        let names = get_names(&app);
+
+        // Moving down skips the subtask and keeps the task selected
+        app.move_task_down();
+        assert_eq!(app.sorting_order, AppSorting::Manual);
+        assert_eq!(
+            get_names(&app),
+            vec![
+                names[2].clone(),
+                names[0].clone(),
+                names[1].clone(),
+                names[3].clone()
+            ]
+        );
+        assert_eq!(app.get_selected_item().unwrap().name, names[0]);
+        app.move_task_up();
+        app.move_task_up();
+        assert_eq!(get_names(&app), names);
+
+        // Custom order survives reopening, new tasks go to its end
+        app.move_task_down();
+        let moved = get_names(&app);
+        let mut app = App::new(app.list.items.clone());
+        assert_eq!(app.sorting_order, AppSorting::Manual);
+        assert_eq!(get_names(&app), moved);
+        app.item_name_input.set_text("D");
+        app.add_new_item();
+        let app = App::new(app.list.items.clone());
+        assert_eq!(get_names(&app).last().unwrap(), "D");
+    }
+
+    #[test]
+    fn it_changes_priority_and_sorts_by_it() {
+        let mut items = vec![TodoItem::new("A"), TodoItem::new("B"), TodoItem::new("C")];
+        for (index, item) in items.iter_mut().enumerate() {
+            item.updated_date = Utc.timestamp(index as i64, 0);
+        }
+        let mut app = App::new(items);
+
+        // Select C and raise it to medium
+        app.list.state.select(Some(2));
+        app.raise_priority();
+        app.raise_priority();
+        assert_eq!(app.list.items[2].priority, Priority::Medium);
+        app.list.state.select(Some(1));
+        app.raise_priority();
+        app.lower_priority();
+        assert_eq!(app.list.items[1].priority, Priority::None);
+        assert!(app.has_unsaved_changes());
+
+        app.set_sorting_order(AppSorting::ByPriority(SortingOrder::Ascending));
+        let names: Vec<&str> = app
+            .list
+            .items
+            .iter()
+            .map(|item| item.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["C", "A", "B"]);
+
+        app.set_sorting_order(AppSorting::ByPriority(SortingOrder::Descending));
+        let names: Vec<&str> = app
+            .list
+            .items
+            .iter()
+            .map(|item| item.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["A", "B", "C"]);
+    }
+
+    #[test]
+    fn it_sorts_by_due_date() {
+        let mut app = App::new(vec![]);
+        for input in &["C", "B @2024-05-02", "A @2024-05-01 17:00"] {
+            app.item_name_input.set_text(input);
+            app.add_new_item();
+        }
+
+        app.set_sorting_order(AppSorting::ByDueDate(SortingOrder::Ascending));
+        let names: Vec<&str> = app
+            .list
+            .items
+            .iter()
+            .map(|item| item.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["A", "B", "C"]);
+
+        app.set_sorting_order(AppSorting::ByDueDate(SortingOrder::Descending));
+        let names: Vec<&str> = app
+            .list
+            .items
+            .iter()
+            .map(|item| item.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["B", "A", "C"]);
+
+        // Due date is kept when the task is edited
+        app.list.state.select(Some(0));
+        app.set_stage(AppStage::UpdateItem);
+        assert_eq!(app.item_name_input.get_text(), "B @2024-05-02");
+    }
+
     #[test]
     fn it_filters_items() {
         let items = create_todo_items();
         let mut app = App::new(items);
 
-        app.filter_term_add_character('a');
-        assert_eq!(app.filter_term, "a");
+        app.filter_term.handle_key(Key::Char('a'));
+        assert_eq!(app.filter_term.get_text(), "a");
         assert_eq!(app.list.items.len(), 2);
-        assert_eq!(app.get_filtered_items().len(), 1);
+        assert_eq!(app.get_filtered_matches().len(), 1);
     }
 
     #[test]
@@ -305,17 +1134,248 @@ mod tests {
         let items = create_todo_items();
         let mut app = App::new(items);
 
-        app.filter_term_add_character('b');
-        assert_eq!(app.filter_term, "b");
-        assert_eq!(app.get_filtered_items()[0].0, 1);
-        assert_eq!(app.get_filtered_items()[0].1.name, TASK_B_NAME);
+        app.filter_term.handle_key(Key::Char('b'));
+        assert_eq!(app.filter_term.get_text(), "b");
+        assert_eq!(app.get_filtered_matches()[0].0, 1);
+        assert_eq!(app.get_filtered_matches()[0].1.name, TASK_B_NAME);
+    }
+
+    #[test]
+    fn it_ranks_fuzzy_matches() {
+        let items = vec![
+            TodoItem::new("buy ice and lollies"),
+            TodoItem::new("pay electricity bill"),
+            TodoItem::new("call mom"),
+        ];
+        let mut app = App::new(items);
+        app.filter_term.set_text("bill");
+        assert_eq!(app.get_filtered_matches().len(), 1);
+
+        app.toggle_fuzzy_filter();
+        let matches = app.get_filtered_matches();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].1.name, "pay electricity bill");
+        assert_eq!(matches[0].2, (16..20).collect());
+        assert_eq!(matches[1].1.name, "buy ice and lollies");
+    }
+
+    #[test]
+    fn it_adds_collapses_and_completes_subtasks() {
+        let mut app = App::new(create_todo_items());
+        app.cascade_completion = true;
+
+        // Subtask of A is shown right after it, B moves down
+        app.set_stage(AppStage::CreateSubItem);
+        assert_eq!(app.get_stage_clone(), AppStage::CreateSubItem);
+        app.item_name_input.set_text("A1");
+        app.add_new_subitem();
+        let rows = app.get_visible_rows();
+        assert_eq!(rows[1].item.name, "A1");
+        assert_eq!(rows[1].depth, 1);
+        assert_eq!(rows[0].progress, Some((0, 1)));
+
+        app.select_next();
+        app.select_next();
+        assert_eq!(app.get_selected_item().unwrap().name, TASK_B_NAME);
+
+        // Collapsing a subtask selects its parent, collapsing the parent hides the subtask
+        app.select_previous();
+        app.collapse_task();
+        assert_eq!(app.get_selected_item().unwrap().name, TASK_A_NAME);
+        app.collapse_task();
+        assert_eq!(app.get_visible_rows().len(), 2);
+        app.expand_task();
+        assert_eq!(app.get_visible_rows().len(), 3);
+
+        app.toggle_task();
+        assert!(app
+            .list
+            .items
+            .iter()
+            .all(|item| item.completed != (item.name == TASK_B_NAME)));
+
+        app.remove_task();
+        assert_eq!(app.list.items.len(), 1);
+    }
+
+    #[test]
+    fn it_spawns_next_occurrence_of_recurring_task() {
+        let mut app = App::new(vec![]);
+        app.item_name_input.set_text("update dependencies *weekly");
+        app.add_new_item();
+        app.select_next();
+
+        app.toggle_task();
+        assert_eq!(app.list.items.len(), 2);
+        let next = &app.list.items[1];
+        assert!(!next.completed);
+        assert!(next.due.is_some());
+        assert!(matches!(
+            app.status_message,
+            Some(StatusMessage::Info(ref message)) if message.starts_with("Next")
+        ));
+    }
+
+    #[test]
+    fn it_applies_changes_to_marked_tasks() {
+        let items = vec![TodoItem::new("A"), TodoItem::new("B"), TodoItem::new("C")];
+        let mut app = App::new(items);
+
+        // Range from the first row to the second one, then the third row on its own
+        app.toggle_range_selection();
+        app.select_next();
+        assert_eq!(app.get_marked_ids().len(), 2);
+        app.toggle_range_selection();
+        app.select_next();
+        app.toggle_mark();
+        app.toggle_mark();
+        assert_eq!(app.get_marked_ids().len(), 2);
+
+        app.raise_priority();
+        assert!(app.marked.is_empty());
+        let raised: Vec<Priority> = app.list.items.iter().map(|item| item.priority).collect();
+        assert_eq!(raised, vec![Priority::Low, Priority::Low, Priority::None]);
+
+        app.marked = app.list.items.iter().map(|item| item.id).collect();
+        app.item_name_input.set_text("#work home -#none");
+        app.tag_tasks();
+        assert!(app.list.items.iter().all(|item| item.tags.len() == 2));
+
+        app.marked = app.list.items.iter().take(2).map(|item| item.id).collect();
+        app.toggle_task();
+        app.undo();
+        assert_eq!(
+            app.status_message,
+            Some(StatusMessage::Info("Undone: complete 2 tasks".to_string()))
+        );
+
+        app.marked = app.list.items.iter().take(2).map(|item| item.id).collect();
+        app.remove_task();
+        assert_eq!(app.list.items.len(), 1);
+    }
+
+    #[test]
+    fn it_completes_mixed_marked_tasks() {
+        let mut done = TodoItem::new("A");
+        done.set_completion(true);
+        let done_date = Utc.ymd(2024, 1, 5).and_hms(12, 0, 0);
+        done.completed_date = Some(done_date);
+        done.updated_date = done_date;
+        let mut app = App::new(vec![done, TodoItem::new("B")]);
+
+        app.marked = app.list.items.iter().map(|item| item.id).collect();
+        app.toggle_task();
+
+        assert!(app.list.items.iter().all(|item| item.completed));
+        assert_eq!(app.list.items[0].completed_date, Some(done_date));
+        assert_eq!(app.list.items[0].updated_date, done_date);
+        assert_ne!(app.list.items[1].completed_date, Some(done_date));
+    }
+
+    #[test]
+    fn it_navigates_rows() {
+        let items = (1..=10).map(|n| TodoItem::new(&n.to_string())).collect();
+        let mut app = App::new(items);
+
+        app.select_by(4);
+        assert_eq!(app.list.state.selected(), Some(4));
+        app.select_by(20);
+        assert_eq!(app.list.state.selected(), Some(9));
+        app.select_first();
+        assert_eq!(app.list.state.selected(), Some(0));
+        app.select_last();
+        assert_eq!(app.list.state.selected(), Some(9));
+
+        app.filter_term.set_text("1");
+        app.select_task_number(10);
+        assert_eq!(app.get_selected_item().unwrap().name, "10");
+        app.select_task_number(3);
+        assert_eq!(app.get_selected_item().unwrap().name, "10");
+        assert!(app.status_message.is_some());
+    }
+
+    #[test]
+    fn it_sets_notes() {
+        let mut app = App::new(create_todo_items());
+        let id = app.get_selected_item().unwrap().id;
+
+        app.set_notes(id, "Ask Bob\nfor access");
+        assert_eq!(app.list.items[0].notes, "Ask Bob\nfor access");
+        assert!(app.has_unsaved_changes());
+
+        // Unchanged notes from a closed editor are not a change
+        app.mark_as_saved();
+        app.set_notes(id, "Ask Bob\nfor access");
+        assert!(!app.has_unsaved_changes());
+    }
+
+    #[test]
+    fn it_undoes_and_redoes_changes() {
+        let mut app = App::new(create_todo_items());
+
+        app.remove_task();
+        app.toggle_task();
+        assert_eq!(app.list.items.len(), 1);
+        assert!(app.list.items[0].completed);
+
+        app.undo();
+        assert!(!app.list.items[0].completed);
+        app.undo();
+        assert_eq!(app.list.items.len(), 2);
+        assert_eq!(
+            app.status_message,
+            Some(StatusMessage::Info(format!(
+                "Undone: remove \"{}\"",
+                TASK_A_NAME
+            )))
+        );
+        app.undo();
+        assert_eq!(
+            app.status_message,
+            Some(StatusMessage::Info("Nothing to undo".to_string()))
+        );
+
+        app.redo();
+        assert_eq!(app.list.items.len(), 1);
+        assert_eq!(app.get_selected_item().unwrap().name, TASK_B_NAME);
+        assert!(app.has_unsaved_changes());
+    }
+
+    #[test]
+    fn it_shows_archive_instead_of_list() {
+        let mut app = App::new(create_todo_items());
+        let mut archived = TodoItem::new("archived");
+        archived.set_completion(true);
+
+        app.open_archive(vec![archived]);
+        let rows = app.get_visible_rows();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(app.get_selected_item().unwrap().name, "archived");
+
+        app.close_archive();
+        assert_eq!(app.get_visible_rows().len(), 2);
+
+        app.toggle_task();
+        app.apply_archive(vec![app.list.items[1].clone()], 1);
+        assert_eq!(app.list.items.len(), 1);
+        assert_eq!(
+            app.status_message,
+            Some(StatusMessage::Info("Archived 1 task".to_string()))
+        );
+        app.undo();
+        assert_eq!(app.list.items.len(), 1);
     }
 
     fn create_todo_items() -> Vec<TodoItem> {
-        vec![
-            TodoItem::new(&(*TASK_A_NAME)),
-            TodoItem::new(&(*TASK_B_NAME)),
-        ]
+        vec![TodoItem::new(TASK_A_NAME), TodoItem::new(TASK_B_NAME)]
+    }
+}
+
+// Names a single task or counts several of them in change descriptions
+fn describe_tasks(items: &[TodoItem]) -> String {
+    match items {
+        [item] => format!("\"{}\"", item.name),
+        items => format!("{} tasks", items.len()),
     }
 }
 
@@ -323,6 +1383,10 @@ mod tests {
 pub enum AppSorting {
     ByDate(SortingOrder),
     ByCompletion(SortingOrder),
+    ByDueDate(SortingOrder),
+    ByPriority(SortingOrder),
+    // Order set by moving tasks, kept in their `position`
+    Manual,
 }
 
 impl Display for AppSorting {
@@ -335,6 +1399,11 @@ impl Display for AppSorting {
                 AppSorting::ByDate(SortingOrder::Descending) => "Least recently updated first",
                 AppSorting::ByCompletion(SortingOrder::Ascending) => "Done first",
                 AppSorting::ByCompletion(SortingOrder::Descending) => "Undone first",
+                AppSorting::ByDueDate(SortingOrder::Ascending) => "Due soonest first",
+                AppSorting::ByDueDate(SortingOrder::Descending) => "Due latest first",
+                AppSorting::ByPriority(SortingOrder::Ascending) => "Highest priority first",
+                AppSorting::ByPriority(SortingOrder::Descending) => "Lowest priority first",
+                AppSorting::Manual => "Custom order",
             }
         )
     }
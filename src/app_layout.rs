@@ -1,14 +1,24 @@
-use crate::app::{App, AppSorting, AppStage};
+use crate::app::{App, AppSorting, AppStage, StatusMessage};
+use crate::due_date::get_local_now;
+use crate::keymap::{Action, Keymap};
+use crate::task_tree::ListRow;
+use crate::text_input::TextInput;
+use chrono::{DateTime, Local, Utc};
+use std::collections::HashSet;
 use tui::backend::Backend;
 use tui::layout::{Alignment, Constraint, Direction, Layout, Rect};
 use tui::style::{Color, Modifier, Style};
+use tui::text::{Span, Spans};
 use tui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
 use tui::Frame;
+use uuid::Uuid;
 
 pub struct ListLayout<'a> {
     pub layout: Layout,
     pub new_item_input_block: Block<'a>,
     pub list_block: Block<'a>,
+    pub list_switcher_block: Block<'a>,
+    pub details_block: Block<'a>,
 }
 
 impl<'a> ListLayout<'a> {
@@ -17,6 +27,8 @@ impl<'a> ListLayout<'a> {
             layout: Layout::default(),
             new_item_input_block: Block::default(),
             list_block: Block::default(),
+            list_switcher_block: Block::default(),
+            details_block: Block::default(),
         }
     }
 
@@ -24,12 +36,20 @@ impl<'a> ListLayout<'a> {
         &mut self,
         stage: &AppStage,
         sorting: &AppSorting,
+        list_title: &str,
+        show_details: bool,
         area: Rect,
     ) -> Vec<Rect> {
         let constraint: Vec<Constraint> = match stage {
-            AppStage::CreateItem | AppStage::UpdateItem => {
+            AppStage::CreateItem
+            | AppStage::CreateSubItem
+            | AppStage::UpdateItem
+            | AppStage::TagItems
+            | AppStage::SwitchList
+            | AppStage::MoveToList => {
                 vec![Constraint::Percentage(50), Constraint::Percentage(50)]
             }
+            _ if show_details => vec![Constraint::Percentage(50), Constraint::Percentage(50)],
             _ => vec![Constraint::Percentage(100)],
         };
 
@@ -41,10 +61,13 @@ impl<'a> ListLayout<'a> {
         self.list_block = Block::default()
             .borders(Borders::ALL)
             .border_style(Style::default().fg(border_color))
-            .title(format!("My tasks  |  Sorting: {}", sorting));
+            .title(format!("{}  |  Sorting: {}", list_title, sorting));
 
         let border_color = match stage {
-            AppStage::CreateItem | AppStage::UpdateItem => Color::Green,
+            AppStage::CreateItem
+            | AppStage::CreateSubItem
+            | AppStage::UpdateItem
+            | AppStage::TagItems => Color::Green,
             _ => Color::Reset,
         };
 
@@ -52,27 +75,102 @@ impl<'a> ListLayout<'a> {
             .borders(Borders::ALL)
             .title(match stage {
                 AppStage::CreateItem => "Create task",
+                AppStage::CreateSubItem => "Create subtask",
                 AppStage::UpdateItem => "Edit task",
+                AppStage::TagItems => "Tag tasks",
                 _ => "",
             })
             .border_style(Style::default().fg(border_color));
 
+        self.list_switcher_block = Block::default()
+            .borders(Borders::ALL)
+            .title(match stage {
+                AppStage::MoveToList => "Move to list",
+                _ => "Lists",
+            })
+            .border_style(Style::default().fg(Color::Green));
+
+        self.details_block = Block::default().borders(Borders::ALL).title("Details");
+
         self.layout = Layout::default()
             .direction(Direction::Horizontal)
-            .constraints(constraint.as_ref());
+            .constraints(constraint);
 
         self.layout.split(area)
     }
 
+    /// Draws the task tree together with task positions in the list, highlighting the name
+    /// characters matched by the filter and the marked tasks.
     pub fn draw_list_widget<B>(
         &self,
         frame: &mut Frame<B>,
-        items: Vec<ListItem<'a>>,
+        rows: &[ListRow],
+        marked: &HashSet<Uuid>,
         area: Rect,
         state: &mut ListState,
     ) where
         B: Backend,
     {
+        let now = get_local_now();
+        let items: Vec<ListItem> = rows
+            .iter()
+            .map(|row| {
+                let item = &row.item;
+                let color = match item.due {
+                    Some(due) if !item.completed && due.is_overdue(now) => Color::Red,
+                    Some(due) if !item.completed && due.is_due_today(now.date()) => Color::Yellow,
+                    _ => Color::Reset,
+                };
+                let style = Style::default().fg(color);
+
+                let tree_marker = match row.progress {
+                    Some(_) if row.is_collapsed => "▸ ",
+                    Some(_) => "▾ ",
+                    None => "",
+                };
+                let mut spans = vec![Span::styled(
+                    format!(
+                        "{}. {}{}{}",
+                        row.index + 1,
+                        "  ".repeat(row.depth),
+                        tree_marker,
+                        item.get_row_prefix()
+                    ),
+                    style,
+                )];
+                let match_style = style
+                    .fg(Color::Magenta)
+                    .add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+                for (position, c) in item.name.chars().enumerate() {
+                    if row.matched_positions.contains(&position) {
+                        spans.push(Span::styled(c.to_string(), match_style));
+                    } else {
+                        spans.push(Span::styled(c.to_string(), style));
+                    }
+                }
+                if let Some((done, total)) = row.progress {
+                    spans.push(Span::styled(format!(" {}/{}", done, total), style));
+                }
+                for tag in item.get_tag_strings() {
+                    spans.push(Span::raw(" "));
+                    spans.push(Span::styled(tag, Style::default().fg(Color::Cyan)));
+                }
+                if let Some(due) = item.get_due_string(now.date()) {
+                    spans.push(Span::styled(format!("  {}", due), style));
+                }
+                if let Some(recurrence) = item.get_recurrence_string() {
+                    spans.push(Span::styled(format!(" {}", recurrence), style));
+                }
+
+                let list_item = ListItem::new(Spans::from(spans));
+                if marked.contains(&item.id) {
+                    list_item.style(Style::default().bg(Color::Blue))
+                } else {
+                    list_item
+                }
+            })
+            .collect();
+
         frame.render_stateful_widget(
             self.get_list_widget(items, self.list_block.clone()),
             area,
@@ -91,19 +189,122 @@ impl<'a> ListLayout<'a> {
             .block(block)
     }
 
-    pub fn draw_item_input_widget<B>(&self, frame: &mut Frame<B>, item_name: &str, area: Rect)
+    pub fn draw_list_switcher_widget<B>(
+        &self,
+        frame: &mut Frame<B>,
+        list_names: &[String],
+        area: Rect,
+        state: &mut ListState,
+    ) where
+        B: Backend,
+    {
+        let items: Vec<ListItem> = list_names
+            .iter()
+            .map(|name| ListItem::new(name.clone()))
+            .collect();
+
+        frame.render_stateful_widget(
+            self.get_list_widget(items, self.list_switcher_block.clone()),
+            area,
+            state,
+        );
+    }
+
+    /// Draws notes and metadata of the selected task.
+    pub fn draw_details_widget<B>(
+        &self,
+        frame: &mut Frame<B>,
+        row: Option<&ListRow>,
+        keymap: &Keymap,
+        area: Rect,
+    ) where
+        B: Backend,
+    {
+        let row = match row {
+            Some(row) => row,
+            None => {
+                frame.render_widget(
+                    Paragraph::new("No task selected").block(self.details_block.clone()),
+                    area,
+                );
+                return;
+            }
+        };
+        let item = &row.item;
+        let today = get_local_now().date();
+        let format_date = |date: DateTime<Utc>| {
+            date.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        };
+        let label_style = Style::default().fg(Color::DarkGray);
+        let field = |label: &str, value: String| {
+            Spans::from(vec![
+                Span::styled(format!("{:<10}", label), label_style),
+                Span::raw(value),
+            ])
+        };
+
+        let mut lines = vec![
+            Spans::from(Span::styled(
+                item.name.clone(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Spans::from(""),
+            field(
+                "Status",
+                if item.completed { "done" } else { "open" }.to_string(),
+            ),
+            field("Priority", item.priority.to_string()),
+        ];
+        if let Some(due) = item.due {
+            lines.push(field("Due", due.to_display_string(today)));
+        }
+        if let Some(recurrence) = &item.recurrence {
+            lines.push(field("Repeats", recurrence.to_input_string()));
+        }
+        if !item.tags.is_empty() {
+            lines.push(field("Tags", item.get_tag_strings().join(" ")));
+        }
+        if let Some((done, total)) = row.progress {
+            lines.push(field("Subtasks", format!("{}/{} done", done, total)));
+        }
+        lines.push(field("Created", format_date(item.created_date)));
+        lines.push(field("Updated", format_date(item.updated_date)));
+        lines.push(field("Id", item.id.to_string()));
+        lines.push(Spans::from(""));
+        if item.notes.is_empty() {
+            let hint = match keymap.get_keys_text(Action::Notes) {
+                Some(keys) => format!("No notes, press {} to write them", keys),
+                None => "No notes".to_string(),
+            };
+            lines.push(Spans::from(Span::styled(hint, label_style)));
+        } else {
+            lines.extend(item.notes.lines().map(|line| Spans::from(line.to_string())));
+        }
+
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(self.details_block.clone())
+                .wrap(Wrap { trim: false }),
+            area,
+        );
+    }
+
+    pub fn draw_item_input_widget<B>(&self, frame: &mut Frame<B>, input: &TextInput, area: Rect)
     where
         B: Backend,
     {
         frame.render_widget(
-            self.get_item_input_widget(item_name, self.new_item_input_block.clone()),
+            self.get_item_input_widget(input.get_text(), self.new_item_input_block.clone()),
             area,
         );
+        // Borders take a cell on each side
         let padding = 1;
-        let item_length: u16 = item_name.len() as u16;
-        let line_length = area.x - padding * 2;
-        let offset_y = (item_length / line_length) + padding;
-        let offset_x = item_length - (item_length / line_length * line_length) + padding;
+        let line_length = area.width.saturating_sub(padding * 2).max(1) as usize;
+        let cursor_width = input.get_cursor_width();
+        let offset_y = (cursor_width / line_length) as u16 + padding;
+        let offset_x = (cursor_width % line_length) as u16 + padding;
         frame.set_cursor(area.x + offset_x, area.y + offset_y);
     }
 
@@ -141,47 +342,110 @@ impl<'a> AppLayout<'a> {
 
         self.layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints(constraint.as_ref());
+            .constraints(constraint);
 
-        let border_color = match stage {
-            AppStage::Filter => Color::Green,
-            _ => Color::Reset,
+        let title = if app.is_fuzzy_filter {
+            "Filter (fuzzy)"
+        } else {
+            "Filter"
+        };
+        let (title, border_color) = match (app.get_filter_query(), stage) {
+            (Err(error), _) => (format!("{} - {}", title, error), Color::Red),
+            (Ok(_), AppStage::Filter) => (title.to_string(), Color::Green),
+            (Ok(_), _) => (title.to_string(), Color::Reset),
         };
 
         self.filter_block = Block::default()
             .borders(Borders::ALL)
-            .title("Filter")
+            .title(title)
             .border_style(Style::default().fg(border_color));
 
         let app_layout_chunks = self.layout.split(area);
-        let list_layout_chunks =
-            self.list_layout
-                .update_layout_chunks(stage, &app.sorting_order, app_layout_chunks[1]);
+        let marked_count = app.get_marked_ids().len();
+        let list_title = match app.archive {
+            Some(_) => format!("{} - Archive", app.list_title),
+            None if marked_count > 0 => format!("{} - {} marked", app.list_title, marked_count),
+            None => app.list_title.clone(),
+        };
+        let list_layout_chunks = self.list_layout.update_layout_chunks(
+            stage,
+            &app.sorting_order,
+            &list_title,
+            app.show_details,
+            app_layout_chunks[1],
+        );
 
         (app_layout_chunks, list_layout_chunks)
     }
 
-    pub fn draw_help_widget<B>(&self, frame: &mut Frame<B>, stage: &AppStage, area: Rect)
-    where
+    pub fn draw_help_widget<B>(
+        &self,
+        frame: &mut Frame<B>,
+        app: &App,
+        // Help of the task list and key hints in messages are generated from it
+        keymap: &Keymap,
+        area: Rect,
+    ) where
         B: Backend,
     {
+        let (version_text, version_style) = match &app.available_update {
+            Some(version) => (
+                format!("v{} available", version),
+                Style::default().fg(Color::Yellow),
+            ),
+            None => (
+                format!("Rudo v{}", env!("CARGO_PKG_VERSION")),
+                Style::default(),
+            ),
+        };
+
         let info_layout = Layout::default()
             .direction(Direction::Horizontal)
-            .constraints(vec![Constraint::Min(5), Constraint::Length(15)]);
+            .constraints(vec![
+                Constraint::Min(5),
+                Constraint::Length(version_text.len() as u16 + 4),
+            ]);
 
-        let help_block = Block::default().borders(Borders::ALL).title("Help");
+        let mut help_block = Block::default().borders(Borders::ALL).title("Help");
         let version_block = Block::default().borders(Borders::ALL);
 
-        let version_paragraph = Paragraph::new(format!("Rudo v{}", env!("CARGO_PKG_VERSION")))
+        let version_paragraph = Paragraph::new(version_text)
+            .style(version_style)
             .alignment(Alignment::Center);
 
-        let paragraph = match stage {
-            AppStage::Default => {
-                Paragraph::new("q - quit, s - sort, n - new task, e - edit, f - filter task")
+        let paragraph = match (&app.status_message, &*app.stage.lock().unwrap()) {
+            (Some(StatusMessage::SaveError(error)), _) => {
+                help_block = help_block
+                    .title("Error")
+                    .border_style(Style::default().fg(Color::Red));
+                // Ctrl-C quits even if quitting is not bound to any key
+                let quit_keys = keymap
+                    .get_keys_text(Action::Quit)
+                    .unwrap_or_else(|| "Ctrl-C".to_string());
+                Paragraph::new(format!(
+                    "Saving failed: {}. Press {} again to quit without saving",
+                    error, quit_keys
+                ))
+            }
+            (Some(StatusMessage::Error(error)), _) => {
+                help_block = help_block
+                    .title("Error")
+                    .border_style(Style::default().fg(Color::Red));
+                Paragraph::new(error.clone())
             }
-            AppStage::CreateItem => Paragraph::new("Enter - add item"),
-            AppStage::UpdateItem => Paragraph::new("Enter - apply changes"),
-            AppStage::Filter => Paragraph::new("Enter - apply filter"),
+            (Some(StatusMessage::Info(message)), _) => Paragraph::new(message.clone()),
+            (None, AppStage::Default) => Paragraph::new(keymap.get_help(app.archive.is_some())),
+            (None, AppStage::CreateItem) => Paragraph::new("Enter - add item"),
+            (None, AppStage::CreateSubItem) => Paragraph::new("Enter - add subtask"),
+            (None, AppStage::UpdateItem) => Paragraph::new("Enter - apply changes"),
+            (None, AppStage::Filter) => {
+                Paragraph::new("Enter - apply filter, Tab - toggle fuzzy matching")
+            }
+            (None, AppStage::TagItems) => {
+                Paragraph::new("Enter - apply, #tag adds a tag, -#tag removes it, Esc - cancel")
+            }
+            (None, AppStage::SwitchList) => Paragraph::new("Enter - open list, Esc - cancel"),
+            (None, AppStage::MoveToList) => Paragraph::new("Enter - move tasks, Esc - cancel"),
         };
 
         let info_layout_chunks = info_layout.split(area);
@@ -193,18 +457,29 @@ impl<'a> AppLayout<'a> {
         );
     }
 
-    pub fn draw_filter_widget<B>(&self, frame: &mut Frame<B>, filter_term: &str, area: Rect)
-    where
+    pub fn draw_filter_widget<B>(
+        &self,
+        frame: &mut Frame<B>,
+        filter_term: &TextInput,
+        is_editing: bool,
+        area: Rect,
+    ) where
         B: Backend,
     {
         frame.render_widget(
-            self.get_filter_widget(filter_term, self.filter_block.clone()),
+            self.get_filter_widget(filter_term.get_text(), self.filter_block.clone()),
             area,
         );
+        if is_editing {
+            // Text is not wrapped, the cursor stays within the borders
+            let max_offset = area.width.saturating_sub(2);
+            let offset_x = (filter_term.get_cursor_width() as u16).min(max_offset);
+            frame.set_cursor(area.x + 1 + offset_x, area.y + 1);
+        }
     }
 
     fn get_filter_widget(&self, filter_term: &str, block: Block<'a>) -> Paragraph<'a> {
-        let text = if filter_term == "" {
+        let text = if filter_term.is_empty() {
             "None"
         } else {
             filter_term
@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::io;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::location::TaskList;
+use crate::storage::{dump, get_app_data, Data, LoadError};
+use crate::task_tree::get_descendant_ids;
+use crate::todo_item::TodoItem;
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Load(LoadError),
+    Save(io::Error),
+}
+
+impl Display for ArchiveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArchiveError::Load(error) => write!(f, "cannot open archive: {}", error),
+            ArchiveError::Save(error) => write!(f, "cannot save archive: {}", error),
+        }
+    }
+}
+
+/// Removes done tasks from the items and returns them. A subtask is archived together with
+/// its top-level task once all of them are done. With `done_before` only tasks done before
+/// that time are taken.
+pub fn take_done_tasks(
+    items: &mut Vec<TodoItem>,
+    done_before: Option<DateTime<Utc>>,
+) -> Vec<TodoItem> {
+    let ids: HashSet<_> = items.iter().map(|item| item.id).collect();
+    let is_archivable = |item: &TodoItem| match item.get_completed_date() {
+        Some(completed_date) => done_before.is_none_or(|done_before| completed_date < done_before),
+        None => false,
+    };
+
+    let mut archived_ids = HashSet::new();
+    for item in items.iter() {
+        // Tasks with a missing parent count as top-level
+        if item
+            .parent_id
+            .is_some_and(|parent_id| ids.contains(&parent_id))
+        {
+            continue;
+        }
+
+        let mut task_ids = get_descendant_ids(items, item.id);
+        task_ids.insert(item.id);
+        let is_done = items
+            .iter()
+            .filter(|item| task_ids.contains(&item.id))
+            .all(is_archivable);
+        if is_done {
+            archived_ids.extend(task_ids);
+        }
+    }
+
+    let (archived, kept) = items
+        .drain(..)
+        .partition(|item| archived_ids.contains(&item.id));
+    *items = kept;
+
+    archived
+}
+
+/// Adds tasks to the archive of the list. Tasks which are archived already are replaced,
+/// e.g. if the list was not saved after the previous archiving.
+pub fn archive_tasks(task_list: &TaskList, tasks: Vec<TodoItem>) -> Result<(), ArchiveError> {
+    let path = task_list.get_archive_path();
+    let ids: HashSet<_> = tasks.iter().map(|task| task.id).collect();
+    let mut archived = get_app_data(&path).map_err(ArchiveError::Load)?;
+    archived.retain(|task| !ids.contains(&task.id));
+    archived.extend(tasks);
+
+    dump(&path, &Data::new(archived)).map_err(ArchiveError::Save)
+}
+
+/// Archives tasks done more than `after_days` ago, if it is set. Returns the number of
+/// archived tasks, the list should be saved when there are any.
+pub fn archive_old_tasks(
+    task_list: &TaskList,
+    items: &mut Vec<TodoItem>,
+    after_days: Option<u32>,
+) -> Result<usize, ArchiveError> {
+    let after_days = match after_days {
+        Some(after_days) => after_days,
+        None => return Ok(0),
+    };
+
+    let mut kept = items.clone();
+    let archived = take_done_tasks(
+        &mut kept,
+        Some(Utc::now() - Duration::days(after_days as i64)),
+    );
+    let count = archived.len();
+    if count > 0 {
+        // Items are changed only if the archive is saved, so nothing is lost
+        archive_tasks(task_list, archived)?;
+        *items = kept;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::tempdir;
+
+    fn done_at(name: &str, timestamp: i64) -> TodoItem {
+        let mut item = TodoItem::new(name);
+        item.set_completion(true);
+        item.completed_date = Some(Utc.timestamp(timestamp, 0));
+        item
+    }
+
+    #[test]
+    fn it_takes_done_tasks_with_their_subtasks() {
+        let parent = done_at("parent", 100);
+        let mut done_subtask = done_at("done subtask", 100);
+        done_subtask.parent_id = Some(parent.id);
+        let open_parent = TodoItem::new("open parent");
+        let mut subtask_of_open = done_at("subtask of open", 100);
+        subtask_of_open.parent_id = Some(open_parent.id);
+        let mut items = vec![
+            parent,
+            done_subtask,
+            open_parent,
+            subtask_of_open,
+            done_at("recent", 300),
+        ];
+
+        let archived = take_done_tasks(&mut items, Some(Utc.timestamp(200, 0)));
+        let names: Vec<&str> = archived.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, vec!["parent", "done subtask"]);
+        assert_eq!(items.len(), 3);
+
+        let archived = take_done_tasks(&mut items, None);
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].name, "recent");
+    }
+
+    #[test]
+    fn it_appends_to_archive_file() {
+        let dir = tempdir().unwrap();
+        let task_list = TaskList::in_dir(dir.path(), "work");
+        let task = done_at("A", 100);
+
+        archive_tasks(&task_list, vec![task.clone()]).unwrap();
+        archive_tasks(&task_list, vec![task, done_at("B", 100)]).unwrap();
+
+        let archived = get_app_data(&task_list.get_archive_path()).unwrap();
+        assert_eq!(archived.len(), 2);
+
+        let mut items = vec![done_at("C", 100), TodoItem::new("D")];
+        assert_eq!(archive_old_tasks(&task_list, &mut items, None).unwrap(), 0);
+        assert_eq!(
+            archive_old_tasks(&task_list, &mut items, Some(7)).unwrap(),
+            1
+        );
+        assert_eq!(items.len(), 1);
+    }
+}
@@ -0,0 +1,686 @@
+use std::fmt::{Display, Formatter};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use structopt::StructOpt;
+
+use crate::app::App;
+use crate::archive::{archive_old_tasks, archive_tasks, take_done_tasks};
+use crate::config::Config;
+use crate::due_date::get_local_now;
+use crate::item_input::ItemInput;
+use crate::location::TaskList;
+use crate::recurrence::spawn_next_occurrence;
+use crate::storage::{dump, get_app_data, Data, LoadError};
+use crate::task_tree::{remove_with_subtasks, set_completion, ListRow};
+use crate::todo_item::TodoItem;
+use crate::update::{get_update_source, UpdateResult, Updater};
+use crate::utils::confirm;
+
+// Number of uuid characters printed by `rudo list`. Any unique prefix is accepted as an id.
+const SHORT_ID_LENGTH: usize = 8;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "rudo", about = "A geeky terminal todo app")]
+pub struct Cli {
+    /// Opens a task list file instead of a named list, can be set with RUDO_DATA as well
+    #[structopt(long, global = true, parse(from_os_str), conflicts_with = "list")]
+    pub file: Option<PathBuf>,
+    /// Opens a named list from the data directory
+    #[structopt(long, global = true)]
+    pub list: Option<String>,
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub enum Command {
+    /// Adds a new task
+    Add {
+        /// Adds the task as a subtask of the task with this id or its unique prefix
+        #[structopt(long)]
+        parent: Option<String>,
+        #[structopt(required = true)]
+        name: Vec<String>,
+    },
+    /// Prints tasks
+    List {
+        /// Prints only tasks matching the filter, e.g. `#work is:open due:<=today`
+        filter: Option<String>,
+        /// Output format
+        #[structopt(long, default_value = "plain", possible_values = &ListFormat::VARIANTS)]
+        format: ListFormat,
+        /// Matches words in the filter fuzzily and prints the best matches first
+        #[structopt(long)]
+        fuzzy: bool,
+        /// Prints archived tasks instead
+        #[structopt(long)]
+        archived: bool,
+    },
+    /// Marks a task as done, together with its subtasks if `cascade_completion` is on
+    Done {
+        /// Task id or its unique prefix
+        id: String,
+        /// Marks the task as not done instead
+        #[structopt(long)]
+        undo: bool,
+    },
+    /// Removes a task with its subtasks
+    Rm {
+        /// Task id or its unique prefix
+        id: String,
+    },
+    /// Renames a task, its due date, priority, tags and recurrence are kept unless given
+    Edit {
+        /// Task id or its unique prefix
+        id: String,
+        #[structopt(required = true)]
+        name: Vec<String>,
+    },
+    /// Moves done tasks to the archive of the list
+    Archive,
+    /// Updates rudo to the latest release
+    SelfUpdate,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ListFormat {
+    Plain,
+    Json,
+    Ndjson,
+    Tsv,
+}
+
+impl ListFormat {
+    const VARIANTS: [&'static str; 4] = ["plain", "json", "ndjson", "tsv"];
+}
+
+impl FromStr for ListFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "plain" => Ok(ListFormat::Plain),
+            "json" => Ok(ListFormat::Json),
+            "ndjson" => Ok(ListFormat::Ndjson),
+            "tsv" => Ok(ListFormat::Tsv),
+            _ => Err(format!("unknown format '{}'", format)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CliError {
+    EmptyName,
+    InvalidFilter(String),
+    TaskNotFound(String),
+    AmbiguousId(String, usize),
+    Output(String),
+    Update(String),
+    Load(String),
+    Save(String),
+    Archive(String),
+    // Reader of the output went away, e.g. `rudo list | head -1`
+    OutputClosed,
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::EmptyName => write!(f, "task name cannot be empty"),
+            CliError::InvalidFilter(error) => write!(f, "invalid filter: {}", error),
+            CliError::TaskNotFound(id) => write!(f, "no task matches id '{}'", id),
+            CliError::AmbiguousId(id, count) => {
+                write!(
+                    f,
+                    "id '{}' matches {} tasks, use a longer prefix",
+                    id, count
+                )
+            }
+            CliError::Output(error) => write!(f, "cannot write output: {}", error),
+            CliError::Update(error) => write!(
+                f,
+                "error occurred during update, please report it here: \
+                 https://github.com/GlebIrovich/rudo/issues\n{}",
+                error
+            ),
+            CliError::Load(error) => write!(f, "{}", error),
+            CliError::Save(error) => write!(f, "cannot save tasks: {}", error),
+            CliError::Archive(error) => write!(f, "{}", error),
+            CliError::OutputClosed => write!(f, "output closed"),
+        }
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::BrokenPipe => CliError::OutputClosed,
+            _ => CliError::Output(error.to_string()),
+        }
+    }
+}
+
+/// Runs a subcommand against the stored list and saves it if the command changed anything.
+pub fn run(command: Command, task_list: &TaskList, config: &Config) -> Result<(), CliError> {
+    if command == Command::SelfUpdate {
+        return self_update(config).map_err(|error| CliError::Update(error.to_string()));
+    }
+
+    let is_archive = matches!(command, Command::List { archived: true, .. });
+    let path = if is_archive {
+        task_list.get_archive_path()
+    } else {
+        task_list.path.clone()
+    };
+    let mut items = get_app_data(&path).map_err(|error| match error {
+        LoadError::Corrupt(_, _) => {
+            CliError::Load(format!("{}, run rudo without arguments to recover", error))
+        }
+        _ => CliError::Load(error.to_string()),
+    })?;
+    let stdout = io::stdout();
+
+    if !is_archive {
+        let archived_count = archive_old_tasks(
+            task_list,
+            &mut items,
+            config.archive.auto_archive_after_days,
+        )
+        .map_err(|error| CliError::Archive(error.to_string()))?;
+        if archived_count > 0 {
+            save(task_list, &items)?;
+        }
+    }
+
+    let result = match command {
+        Command::Archive => archive(task_list, &mut items, &mut stdout.lock()),
+        command => execute(command, &mut items, config, &mut stdout.lock()),
+    };
+    match result {
+        Ok(true) => save(task_list, &items)?,
+        Ok(false) | Err(CliError::OutputClosed) => {}
+        Err(error) => return Err(error),
+    }
+
+    Ok(())
+}
+
+fn save(task_list: &TaskList, items: &[TodoItem]) -> Result<(), CliError> {
+    dump(&task_list.path, &Data::new(items.to_vec()))
+        .map_err(|error| CliError::Save(error.to_string()))
+}
+
+/// Moves done tasks to the archive file. Returns `true` if there were any.
+fn archive<W: Write>(
+    task_list: &TaskList,
+    items: &mut Vec<TodoItem>,
+    out: &mut W,
+) -> Result<bool, CliError> {
+    let mut kept = items.clone();
+    let archived = take_done_tasks(&mut kept, None);
+    let archived_count = archived.len();
+    if archived_count > 0 {
+        archive_tasks(task_list, archived).map_err(|error| CliError::Archive(error.to_string()))?;
+        *items = kept;
+    }
+
+    // Tasks are archived even if nobody reads the confirmation
+    let _ = writeln!(
+        out,
+        "Archived {} task{}",
+        archived_count,
+        if archived_count == 1 { "" } else { "s" }
+    );
+    Ok(archived_count > 0)
+}
+
+/// Applies a subcommand to the items. Returns `true` if the items were modified.
+fn execute<W: Write>(
+    command: Command,
+    items: &mut Vec<TodoItem>,
+    config: &Config,
+    out: &mut W,
+) -> Result<bool, CliError> {
+    match command {
+        Command::Add { parent, name } => {
+            let mut item = parse_input(&name)?.into_item();
+            if let Some(parent) = parent {
+                item.parent_id = Some(find_item(items, &parent)?.id);
+            }
+            let id = short_id(&item);
+            items.push(item);
+            // The task is added even if nobody reads the confirmation
+            let _ = writeln!(out, "Added {}", id);
+            Ok(true)
+        }
+        Command::List {
+            filter,
+            format,
+            fuzzy,
+            archived: _,
+        } => {
+            let mut app = App::new(items.clone());
+            app.filter_term.set_text(&filter.unwrap_or_default());
+            app.is_fuzzy_filter = fuzzy;
+            if let Err(error) = app.get_filter_query() {
+                return Err(CliError::InvalidFilter(error.to_string()));
+            }
+            // Tasks are printed in the tree order, with ancestors of the matching ones
+            write_rows(&app.get_visible_rows(), &format, out)?;
+            Ok(false)
+        }
+        Command::Done { id, undo } => {
+            let item_id = find_item(items, &id)?.id;
+            set_completion(items, item_id, !undo, config.tasks.cascade_completion);
+            let today = get_local_now().date();
+            if let Some(next) = spawn_next_occurrence(items, item_id, today) {
+                let _ = writeln!(
+                    out,
+                    "Added {} {}",
+                    short_id(&next),
+                    next.get_due_string(today).unwrap_or_default()
+                );
+            }
+            Ok(true)
+        }
+        Command::Rm { id } => {
+            let item_id = find_item(items, &id)?.id;
+            remove_with_subtasks(items, item_id);
+            Ok(true)
+        }
+        Command::Edit { id, name } => {
+            let input = parse_input(&name)?;
+            input.rename(find_item(items, &id)?);
+            Ok(true)
+        }
+        // Handled by `run`, as they work with other files
+        Command::Archive | Command::SelfUpdate => Ok(false),
+    }
+}
+
+fn self_update(config: &Config) -> UpdateResult<()> {
+    let updater = Updater::new(get_update_source(&config.update.source))?;
+
+    let release = match updater.get_newer_release()? {
+        Some(release) => release,
+        None => {
+            println!("Rudo is up to date!");
+            return Ok(());
+        }
+    };
+
+    let question = format!(
+        "Update rudo from v{} to v{}? [Y/n]",
+        updater.current_version, release.version
+    );
+    if !confirm(&question, true)? {
+        return Ok(());
+    }
+
+    updater.install(&release)?;
+    println!("Successfully updated to version {}", release.version);
+
+    Ok(())
+}
+
+fn parse_input(words: &[String]) -> Result<ItemInput, CliError> {
+    let input = ItemInput::parse(&words.join(" "), get_local_now().date());
+    if input.name.is_empty() {
+        return Err(CliError::EmptyName);
+    }
+    Ok(input)
+}
+
+fn find_item<'a>(items: &'a mut [TodoItem], id: &str) -> Result<&'a mut TodoItem, CliError> {
+    let id = id.to_lowercase();
+    let matches: Vec<usize> = items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| item.id.to_string().starts_with(&id))
+        .map(|(index, _)| index)
+        .collect();
+
+    match matches.as_slice() {
+        [] => Err(CliError::TaskNotFound(id)),
+        [index] => Ok(&mut items[*index]),
+        _ => Err(CliError::AmbiguousId(id, matches.len())),
+    }
+}
+
+fn short_id(item: &TodoItem) -> String {
+    item.id.to_string()[..SHORT_ID_LENGTH].to_string()
+}
+
+fn write_rows<W: Write>(
+    rows: &[ListRow],
+    format: &ListFormat,
+    out: &mut W,
+) -> Result<(), CliError> {
+    let items: Vec<&TodoItem> = rows.iter().map(|row| &row.item).collect();
+    match format {
+        ListFormat::Plain => {
+            let today = get_local_now().date();
+            for row in rows {
+                writeln!(
+                    out,
+                    "{} {}{}",
+                    short_id(&row.item),
+                    "  ".repeat(row.depth),
+                    row.item.to_row_string(today)
+                )?;
+            }
+        }
+        ListFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, &items).map_err(io::Error::from)?;
+            writeln!(out)?;
+        }
+        ListFormat::Ndjson => {
+            for item in items {
+                serde_json::to_writer(&mut *out, item).map_err(io::Error::from)?;
+                writeln!(out)?;
+            }
+        }
+        ListFormat::Tsv => {
+            writeln!(
+                out,
+                "id\tname\tcompleted\tcreated_date\tupdated_date\tdue\tpriority\ttags\tparent_id\tnotes"
+            )?;
+            for item in items {
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    item.id,
+                    escape_tsv(&item.name),
+                    item.completed,
+                    item.created_date.to_rfc3339(),
+                    item.updated_date.to_rfc3339(),
+                    item.due
+                        .map(|due| due.to_input_string())
+                        .unwrap_or_default(),
+                    item.priority.get_marker(),
+                    item.get_tag_strings().join(" "),
+                    item.parent_id
+                        .map(|parent_id| parent_id.to_string())
+                        .unwrap_or_default(),
+                    escape_tsv(&item.notes)
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn escape_tsv(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn execute_command(command: Command, items: &mut Vec<TodoItem>) -> Result<String, CliError> {
+        let mut out = vec![];
+        execute(command, items, &Config::default(), &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn it_parses_subcommands() {
+        let cli = Cli::from_iter(&["rudo", "add", "fix", "CI"]);
+        assert_eq!(
+            cli.command,
+            Some(Command::Add {
+                parent: None,
+                name: vec!["fix".to_string(), "CI".to_string()]
+            })
+        );
+
+        let cli = Cli::from_iter(&["rudo"]);
+        assert_eq!(cli.command, None);
+
+        let cli = Cli::from_iter(&["rudo", "list", "--list", "work"]);
+        assert_eq!(cli.list, Some("work".to_string()));
+        assert!(Cli::from_iter_safe(&["rudo", "--list", "work", "--file", "a.json"]).is_err());
+    }
+
+    #[test]
+    fn it_adds_and_lists_items() {
+        let mut items = vec![];
+
+        execute_command(
+            Command::Add {
+                parent: None,
+                name: vec!["fix CI".to_string()],
+            },
+            &mut items,
+        )
+        .unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].name, "fix CI");
+
+        let output = execute_command(
+            Command::List {
+                filter: None,
+                format: ListFormat::Plain,
+                fuzzy: false,
+                archived: false,
+            },
+            &mut items,
+        )
+        .unwrap();
+        assert_eq!(output, format!("{} [ ] - fix CI\n", short_id(&items[0])));
+    }
+
+    #[test]
+    fn it_lists_filtered_items_in_machine_readable_formats() {
+        let mut items = vec![TodoItem::new("Pay\tbills"), TodoItem::new("Call mom")];
+
+        let output = execute_command(
+            Command::List {
+                filter: Some("BILL".to_string()),
+                format: ListFormat::Ndjson,
+                fuzzy: false,
+                archived: false,
+            },
+            &mut items,
+        )
+        .unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let item: TodoItem = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(item.id, items[0].id);
+
+        let output = execute_command(
+            Command::List {
+                filter: None,
+                format: ListFormat::Json,
+                fuzzy: false,
+                archived: false,
+            },
+            &mut items,
+        )
+        .unwrap();
+        let parsed: Vec<TodoItem> = serde_json::from_str(&output).unwrap();
+        assert_eq!(parsed.len(), 2);
+
+        let output = execute_command(
+            Command::List {
+                filter: Some("bills".to_string()),
+                format: ListFormat::Tsv,
+                fuzzy: false,
+                archived: false,
+            },
+            &mut items,
+        )
+        .unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            "id\tname\tcompleted\tcreated_date\tupdated_date\tdue\tpriority\ttags\tparent_id\tnotes"
+        );
+        assert!(lines[1].starts_with(&format!("{}\tPay\\tbills\tfalse\t", items[0].id)));
+
+        assert_eq!(
+            execute_command(
+                Command::List {
+                    filter: Some("is:later".to_string()),
+                    format: ListFormat::Plain,
+                    fuzzy: false,
+                    archived: false,
+                },
+                &mut items,
+            ),
+            Err(CliError::InvalidFilter(
+                "unknown 'is:later', use is:done or is:open".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn it_updates_item_by_id_prefix() {
+        let mut items = vec![TodoItem::new("A")];
+        let id = short_id(&items[0]);
+
+        execute_command(
+            Command::Done {
+                id: id.clone(),
+                undo: false,
+            },
+            &mut items,
+        )
+        .unwrap();
+        assert!(items[0].completed);
+
+        execute_command(
+            Command::Edit {
+                id: id.clone(),
+                name: vec!["B @2024-05-02".to_string()],
+            },
+            &mut items,
+        )
+        .unwrap();
+        assert_eq!(items[0].name, "B");
+        assert_eq!(items[0].due.unwrap().to_input_string(), "2024-05-02");
+
+        execute_command(Command::Rm { id }, &mut items).unwrap();
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn it_keeps_attributes_not_given_when_renaming() {
+        let mut items = vec![];
+        execute_command(
+            Command::Add {
+                parent: None,
+                name: vec!["fix CI !! @2024-05-02 #work *weekly".to_string()],
+            },
+            &mut items,
+        )
+        .unwrap();
+        let id = short_id(&items[0]);
+
+        let rename = |name: &str, items: &mut Vec<TodoItem>| {
+            let name = vec![name.to_string()];
+            execute_command(
+                Command::Edit {
+                    id: id.clone(),
+                    name,
+                },
+                items,
+            )
+            .unwrap();
+        };
+        rename("fix CI on master", &mut items);
+        assert_eq!(
+            items[0].to_input_string(),
+            "fix CI on master #work !! @2024-05-02 *weekly"
+        );
+
+        rename("fix CI #ci !!!", &mut items);
+        assert_eq!(
+            items[0].to_input_string(),
+            "fix CI #ci !!! @2024-05-02 *weekly"
+        );
+    }
+
+    #[test]
+    fn it_adds_and_lists_subtasks() {
+        let mut items = vec![TodoItem::new("release"), TodoItem::new("other")];
+        let id = short_id(&items[0]);
+
+        execute_command(
+            Command::Add {
+                parent: Some(id.clone()),
+                name: vec!["write notes".to_string()],
+            },
+            &mut items,
+        )
+        .unwrap();
+        assert_eq!(items[2].parent_id, Some(items[0].id));
+
+        // Parent is shown for the matching subtask
+        let output = execute_command(
+            Command::List {
+                filter: Some("notes".to_string()),
+                format: ListFormat::Plain,
+                fuzzy: false,
+                archived: false,
+            },
+            &mut items,
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            format!(
+                "{} [ ] - release\n{}   [ ] - write notes\n",
+                id,
+                short_id(&items[2])
+            )
+        );
+
+        execute_command(Command::Rm { id }, &mut items).unwrap();
+        assert_eq!(items.len(), 1);
+    }
+
+    #[test]
+    fn it_archives_done_tasks() {
+        let dir = tempfile::tempdir().unwrap();
+        let task_list = TaskList::in_dir(dir.path(), "work");
+        let mut done = TodoItem::new("A");
+        done.set_completion(true);
+        dump(&task_list.path, &Data::new(vec![done, TodoItem::new("B")])).unwrap();
+
+        run(Command::Archive, &task_list, &Config::default()).unwrap();
+        assert_eq!(get_app_data(&task_list.path).unwrap().len(), 1);
+        let archived = get_app_data(&task_list.get_archive_path()).unwrap();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].name, "A");
+    }
+
+    #[test]
+    fn it_rejects_unknown_and_ambiguous_ids() {
+        let mut items = vec![TodoItem::new("A"), TodoItem::new("B")];
+
+        assert_eq!(
+            execute_command(Command::Rm { id: "".to_string() }, &mut items),
+            Err(CliError::AmbiguousId("".to_string(), 2))
+        );
+        assert_eq!(
+            execute_command(
+                Command::Rm {
+                    id: "not-an-id".to_string()
+                },
+                &mut items
+            ),
+            Err(CliError::TaskNotFound("not-an-id".to_string()))
+        );
+        assert_eq!(items.len(), 2);
+    }
+}
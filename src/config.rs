@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub update: UpdateConfig,
+    pub storage: StorageConfig,
+    pub tasks: TasksConfig,
+    pub archive: ArchiveConfig,
+    // Keys mapped to action names, e.g. `x = "remove"`, checked when the keymap is built
+    pub keymap: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdateConfig {
+    // Looks for a new release in background and shows a notice in the UI when there is one
+    pub check_on_startup: bool,
+    pub source: UpdateSourceConfig,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    // Directory with task lists, `~` is expanded to the home directory
+    pub data_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TasksConfig {
+    // Completing a task completes its subtasks as well
+    pub cascade_completion: bool,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArchiveConfig {
+    // Tasks done that many days ago are moved to the archive when the list is opened
+    pub auto_archive_after_days: Option<u32>,
+}
+
+/// Where releases are downloaded from: `"github"`, `{ http = "<base url>" }`
+/// or `{ local = "<directory>" }`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateSourceConfig {
+    #[default]
+    Github,
+    Http(String),
+    Local(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read(path, error) => {
+                write!(f, "cannot read config {}: {}", path.display(), error)
+            }
+            ConfigError::Parse(path, error) => {
+                write!(f, "invalid config {}: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file. Missing file is not an error, defaults are used instead.
+    pub fn load() -> Result<Config, ConfigError> {
+        let path = match get_config_path() {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(content) => Config::parse(&content).map_err(|error| ConfigError::Parse(path, error)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(error) => Err(ConfigError::Read(path, error)),
+        }
+    }
+
+    fn parse(content: &str) -> Result<Config, toml::de::Error> {
+        toml::from_str(content)
+    }
+}
+
+fn get_config_path() -> Option<PathBuf> {
+    let mut path = dirs::config_dir()?;
+    path.push("rudo");
+    path.push("config.toml");
+
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_uses_defaults_for_empty_config() {
+        let config = Config::parse("").unwrap();
+
+        assert_eq!(config, Config::default());
+        assert!(!config.update.check_on_startup);
+    }
+
+    #[test]
+    fn it_parses_update_section() {
+        let config = Config::parse("[update]\ncheck_on_startup = true").unwrap();
+
+        assert!(config.update.check_on_startup);
+        assert_eq!(config.update.source, UpdateSourceConfig::Github);
+
+        let config = Config::parse("[update]\nsource = { local = \"/srv/rudo\" }").unwrap();
+        assert_eq!(
+            config.update.source,
+            UpdateSourceConfig::Local(PathBuf::from("/srv/rudo"))
+        );
+
+        let config =
+            Config::parse("[update]\nsource = { http = \"https://example.com\" }").unwrap();
+        assert_eq!(
+            config.update.source,
+            UpdateSourceConfig::Http("https://example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn it_parses_storage_section() {
+        let config = Config::parse("[storage]\ndata_dir = \"~/Dropbox/rudo\"").unwrap();
+
+        assert_eq!(
+            config.storage.data_dir,
+            Some(PathBuf::from("~/Dropbox/rudo"))
+        );
+    }
+
+    #[test]
+    fn it_parses_tasks_and_archive_sections() {
+        assert!(!Config::default().tasks.cascade_completion);
+
+        let config = Config::parse("[tasks]\ncascade_completion = true").unwrap();
+        assert!(config.tasks.cascade_completion);
+
+        let config = Config::parse("[archive]\nauto_archive_after_days = 7").unwrap();
+        assert_eq!(config.archive.auto_archive_after_days, Some(7));
+    }
+
+    #[test]
+    fn it_parses_keymap_section() {
+        let config = Config::parse("[keymap]\nx = \"remove\"\n\"ctrl-n\" = \"new_task\"").unwrap();
+
+        assert_eq!(config.keymap.len(), 2);
+        assert_eq!(config.keymap["x"], "remove");
+        assert_eq!(config.keymap["ctrl-n"], "new_task");
+    }
+
+    #[test]
+    fn it_rejects_unknown_keys() {
+        assert!(Config::parse("[update]\ncheck = true").is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const TIME_FORMAT: &str = "%H:%M";
+// Short weekday names as they are typed, starting from Monday
+pub const WEEKDAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// When a task is due, in local time. Tasks without time are due by the end of the day.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DueDate {
+    pub date: NaiveDate,
+    pub time: Option<NaiveTime>,
+}
+
+impl DueDate {
+    /// Parses words following `@` in the task input: a date (`today`, `tomorrow`, `yesterday`,
+    /// a weekday or `2024-05-03`) optionally followed by a time (`17:00`, `5pm`), or a time alone
+    /// meaning today. Returns the due date and the number of words it took.
+    pub fn parse(words: &[&str], today: NaiveDate) -> Option<(DueDate, usize)> {
+        let first_word = words.first()?;
+
+        if let Some(time) = parse_time(first_word) {
+            return Some((
+                DueDate {
+                    date: today,
+                    time: Some(time),
+                },
+                1,
+            ));
+        }
+
+        let date = parse_date(first_word, today)?;
+        match words.get(1).and_then(|word| parse_time(word)) {
+            Some(time) => Some((
+                DueDate {
+                    date,
+                    time: Some(time),
+                },
+                2,
+            )),
+            None => Some((DueDate { date, time: None }, 1)),
+        }
+    }
+
+    pub fn get_deadline(&self) -> NaiveDateTime {
+        self.date
+            .and_time(self.time.unwrap_or_else(|| NaiveTime::from_hms(23, 59, 59)))
+    }
+
+    pub fn is_overdue(&self, now: NaiveDateTime) -> bool {
+        self.get_deadline() < now
+    }
+
+    pub fn is_due_today(&self, today: NaiveDate) -> bool {
+        self.date == today
+    }
+
+    /// Formats the date the way it can be parsed back, e.g. `2024-05-03 17:00`.
+    pub fn to_input_string(self) -> String {
+        self.format_with_date(self.date.format(DATE_FORMAT).to_string())
+    }
+
+    /// Formats the date for reading, e.g. `today 17:00` or `Fri 2024-05-03`.
+    pub fn to_display_string(self, today: NaiveDate) -> String {
+        let date = if self.date == today {
+            "today".to_string()
+        } else if self.date == today.succ() {
+            "tomorrow".to_string()
+        } else if self.date == today.pred() {
+            "yesterday".to_string()
+        } else {
+            self.date.format("%a %Y-%m-%d").to_string()
+        };
+
+        self.format_with_date(date)
+    }
+
+    fn format_with_date(self, date: String) -> String {
+        match self.time {
+            Some(time) => format!("{} {}", date, time.format(TIME_FORMAT)),
+            None => date,
+        }
+    }
+}
+
+/// Current local time, due dates are compared against it.
+pub fn get_local_now() -> NaiveDateTime {
+    Local::now().naive_local()
+}
+
+/// Parses a date word: `today`, `tomorrow`, `yesterday`, a weekday or `2024-05-03`.
+pub fn parse_date(word: &str, today: NaiveDate) -> Option<NaiveDate> {
+    match word.to_lowercase().as_str() {
+        "today" => Some(today),
+        "tomorrow" => Some(today.succ()),
+        "yesterday" => Some(today.pred()),
+        word => match word.parse::<Weekday>() {
+            Ok(weekday) => Some(get_next_weekday(today, weekday)),
+            Err(_) => NaiveDate::parse_from_str(word, DATE_FORMAT).ok(),
+        },
+    }
+}
+
+// Weekday always means the coming one, `@fri` on Friday is a week later
+fn get_next_weekday(today: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let days_ahead =
+        (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday() - 1) % 7 + 1;
+
+    today + Duration::days(days_ahead as i64)
+}
+
+fn parse_time(word: &str) -> Option<NaiveTime> {
+    let word = word.to_lowercase();
+    if word.ends_with("am") || word.ends_with("pm") {
+        let (time, period) = word.split_at(word.len() - 2);
+        let time = if time.contains(':') {
+            time.to_string()
+        } else {
+            format!("{}:00", time)
+        };
+        return NaiveTime::parse_from_str(&format!("{}{}", time, period), "%I:%M%p").ok();
+    }
+
+    NaiveTime::parse_from_str(&word, TIME_FORMAT).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Wednesday
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd(2024, 5, 1)
+    }
+
+    fn parse(input: &str) -> Option<(DueDate, usize)> {
+        let words: Vec<&str> = input.split_whitespace().collect();
+        DueDate::parse(&words, today())
+    }
+
+    #[test]
+    fn it_parses_dates_and_times() {
+        let due = |date: NaiveDate, time: Option<(u32, u32)>| DueDate {
+            date,
+            time: time.map(|(hour, minute)| NaiveTime::from_hms(hour, minute, 0)),
+        };
+
+        assert_eq!(parse("tomorrow"), Some((due(today().succ(), None), 1)));
+        assert_eq!(
+            parse("fri 17:00"),
+            Some((due(NaiveDate::from_ymd(2024, 5, 3), Some((17, 0))), 2))
+        );
+        assert_eq!(
+            parse("Wednesday"),
+            Some((due(NaiveDate::from_ymd(2024, 5, 8), None), 1))
+        );
+        assert_eq!(parse("5pm"), Some((due(today(), Some((17, 0))), 1)));
+        assert_eq!(
+            parse("2024-06-10 9:30am lunch"),
+            Some((due(NaiveDate::from_ymd(2024, 6, 10), Some((9, 30))), 2))
+        );
+        assert_eq!(parse("someday"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn it_detects_overdue_dates() {
+        let now = today().and_hms(12, 0, 0);
+        let date_only = DueDate {
+            date: today(),
+            time: None,
+        };
+        let morning = DueDate {
+            date: today(),
+            time: Some(NaiveTime::from_hms(9, 0, 0)),
+        };
+
+        assert!(!date_only.is_overdue(now));
+        assert!(date_only.is_due_today(today()));
+        assert!(morning.is_overdue(now));
+        assert!(DueDate {
+            date: today().pred(),
+            time: None
+        }
+        .is_overdue(now));
+    }
+
+    #[test]
+    fn it_formats_dates() {
+        let (due, _) = parse("fri 17:00").unwrap();
+
+        assert_eq!(due.to_input_string(), "2024-05-03 17:00");
+        assert_eq!(due.to_display_string(today()), "Fri 2024-05-03 17:00");
+        assert_eq!(parse(&due.to_input_string()), Some((due, 2)));
+        assert_eq!(
+            parse("tomorrow").unwrap().0.to_display_string(today()),
+            "tomorrow"
+        );
+    }
+}
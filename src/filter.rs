@@ -0,0 +1,444 @@
+use std::fmt::{Display, Formatter};
+
+use std::collections::BTreeSet;
+
+use chrono::{Local, NaiveDate};
+
+use crate::due_date::parse_date;
+use crate::fuzzy::{chars_equal, fuzzy_match};
+use crate::item_input::parse_tag;
+use crate::todo_item::TodoItem;
+
+// Put in front of a term to negate it, e.g. `-#blocked`
+const NEGATION_PREFIX: char = '-';
+const OR_KEYWORD: &str = "OR";
+
+/// Parsed filter, e.g. `#work -is:done due:<=today OR "weekly report"`.
+/// Terms separated by spaces must all match, `OR` has lower precedence.
+#[derive(Debug, PartialEq)]
+pub enum Query {
+    // Case-insensitive substring of the name
+    Text(String),
+    Tag(String),
+    IsDone(bool),
+    Due(Comparison, NaiveDate),
+    Created(Comparison, NaiveDate),
+    Not(Box<Query>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn test(self, value: NaiveDate, expected: NaiveDate) -> bool {
+        match self {
+            Comparison::Less => value < expected,
+            Comparison::LessOrEqual => value <= expected,
+            Comparison::Equal => value == expected,
+            Comparison::GreaterOrEqual => value >= expected,
+            Comparison::Greater => value > expected,
+        }
+    }
+}
+
+/// Why an item matched the query.
+#[derive(Debug, Default, PartialEq)]
+pub struct QueryMatch {
+    // Higher is better, only fuzzy matching scores
+    pub score: i64,
+    // Indexes of the name characters matched by text terms
+    pub positions: BTreeSet<usize>,
+}
+
+impl QueryMatch {
+    fn combine(mut self, other: QueryMatch) -> QueryMatch {
+        self.score += other.score;
+        self.positions.extend(other.positions);
+        self
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct QueryError(String);
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    Not,
+    Or,
+    Open,
+    Close,
+}
+
+impl Query {
+    /// Parses the filter. Relative dates, e.g. `due:<today`, are resolved against `today`.
+    pub fn parse(term: &str, today: NaiveDate) -> Result<Query, QueryError> {
+        let tokens = tokenize(term)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            today,
+        };
+
+        let query = parser.parse_or()?;
+        // Only an unmatched `)` stops parsing before the end
+        match parser.peek() {
+            None => Ok(query),
+            Some(_) => Err(QueryError("unexpected ')'".to_string())),
+        }
+    }
+
+    /// Returns `None` if the item does not match. With `is_fuzzy` text terms match
+    /// subsequences of the name, e.g. `wkrp` matches `weekly report`.
+    pub fn get_match(&self, item: &TodoItem, is_fuzzy: bool) -> Option<QueryMatch> {
+        let matches_if = |is_matching: bool| {
+            if is_matching {
+                Some(QueryMatch::default())
+            } else {
+                None
+            }
+        };
+
+        match self {
+            Query::Text(text) if is_fuzzy => {
+                fuzzy_match(text, &item.name).map(|fuzzy_match| QueryMatch {
+                    score: fuzzy_match.score,
+                    positions: fuzzy_match.positions.into_iter().collect(),
+                })
+            }
+            Query::Text(text) => find_text(text, &item.name).map(|positions| QueryMatch {
+                score: 0,
+                positions,
+            }),
+            Query::Tag(tag) => matches_if(item.tags.contains(tag)),
+            Query::IsDone(is_done) => matches_if(item.completed == *is_done),
+            Query::Due(comparison, date) => {
+                matches_if(item.due.is_some_and(|due| comparison.test(due.date, *date)))
+            }
+            Query::Created(comparison, date) => matches_if(comparison.test(
+                item.created_date.with_timezone(&Local).naive_local().date(),
+                *date,
+            )),
+            Query::Not(query) => matches_if(query.get_match(item, is_fuzzy).is_none()),
+            Query::And(queries) => queries
+                .iter()
+                .try_fold(QueryMatch::default(), |result, query| {
+                    Some(result.combine(query.get_match(item, is_fuzzy)?))
+                }),
+            Query::Or(queries) => queries
+                .iter()
+                .filter_map(|query| query.get_match(item, is_fuzzy))
+                .max_by_key(|query_match| query_match.score),
+        }
+    }
+}
+
+// Case-insensitive substring search returning the matched character indexes
+fn find_text(text: &str, name: &str) -> Option<BTreeSet<usize>> {
+    let text: Vec<char> = text.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    if text.len() > name.len() {
+        return None;
+    }
+
+    (0..=name.len() - text.len())
+        .find(|&start| {
+            text.iter()
+                .zip(&name[start..])
+                .all(|(&a, &b)| chars_equal(a, b))
+        })
+        .map(|start| (start..start + text.len()).collect())
+}
+
+fn tokenize(term: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = vec![];
+    let mut chars = term.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                let mut phrase = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => phrase.push(c),
+                        None => return Err(QueryError("missing closing quote".to_string())),
+                    }
+                }
+                tokens.push(Token::Phrase(phrase));
+            }
+            NEGATION_PREFIX => {
+                chars.next();
+                match chars.peek() {
+                    Some(next) if !next.is_whitespace() => tokens.push(Token::Not),
+                    _ => tokens.push(Token::Word(NEGATION_PREFIX.to_string())),
+                }
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(if word == OR_KEYWORD {
+                    Token::Or
+                } else {
+                    Token::Word(word)
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    today: NaiveDate,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Query, QueryError> {
+        let mut alternatives = vec![self.parse_and()?];
+
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            alternatives.push(self.parse_and()?);
+        }
+
+        if alternatives.len() == 1 {
+            return Ok(alternatives.remove(0));
+        }
+        if alternatives.contains(&Query::And(vec![])) {
+            return Err(QueryError("OR needs a filter on both sides".to_string()));
+        }
+        Ok(Query::Or(alternatives))
+    }
+
+    fn parse_and(&mut self) -> Result<Query, QueryError> {
+        let mut terms = vec![];
+
+        while let Some(token) = self.peek() {
+            if *token == Token::Or || *token == Token::Close {
+                break;
+            }
+            terms.push(self.parse_term()?);
+        }
+
+        if terms.len() == 1 {
+            return Ok(terms.remove(0));
+        }
+        Ok(Query::And(terms))
+    }
+
+    fn parse_term(&mut self) -> Result<Query, QueryError> {
+        match self.next() {
+            Some(Token::Not) => match self.peek() {
+                Some(Token::Or) | Some(Token::Close) | None => {
+                    Err(QueryError("nothing to negate after '-'".to_string()))
+                }
+                Some(_) => Ok(Query::Not(Box::new(self.parse_term()?))),
+            },
+            Some(Token::Open) => {
+                let query = self.parse_or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(query),
+                    _ => Err(QueryError("missing ')'".to_string())),
+                }
+            }
+            Some(Token::Phrase(phrase)) => Ok(Query::Text(phrase.to_lowercase())),
+            Some(Token::Word(word)) => self.parse_word(&word),
+            _ => Err(QueryError("unexpected end of filter".to_string())),
+        }
+    }
+
+    fn parse_word(&self, word: &str) -> Result<Query, QueryError> {
+        if let Some(tag) = parse_tag(word) {
+            return Ok(Query::Tag(tag));
+        }
+
+        let (key, value) = match word.split_once(':') {
+            Some(pair) => pair,
+            None => return Ok(Query::Text(word.to_lowercase())),
+        };
+
+        match key {
+            "is" => match value {
+                "done" => Ok(Query::IsDone(true)),
+                "open" => Ok(Query::IsDone(false)),
+                _ => Err(QueryError(format!(
+                    "unknown '{}', use is:done or is:open",
+                    word
+                ))),
+            },
+            "due" => {
+                let (comparison, date) = self.parse_date_comparison(word, value)?;
+                Ok(Query::Due(comparison, date))
+            }
+            "created" => {
+                let (comparison, date) = self.parse_date_comparison(word, value)?;
+                Ok(Query::Created(comparison, date))
+            }
+            _ => Ok(Query::Text(word.to_lowercase())),
+        }
+    }
+
+    fn parse_date_comparison(
+        &self,
+        word: &str,
+        value: &str,
+    ) -> Result<(Comparison, NaiveDate), QueryError> {
+        let (comparison, date) = [
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+            ("=", Comparison::Equal),
+        ]
+        .iter()
+        .find_map(|(operator, comparison)| {
+            value.strip_prefix(operator).map(|date| (*comparison, date))
+        })
+        .unwrap_or((Comparison::Equal, value));
+
+        match parse_date(date, self.today) {
+            Some(date) => Ok((comparison, date)),
+            None => Err(QueryError(format!("invalid date in '{}'", word))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::due_date::DueDate;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd(2024, 5, 1)
+    }
+
+    fn create_item(name: &str, tags: &[&str]) -> TodoItem {
+        let mut item = TodoItem::new(name);
+        item.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        item
+    }
+
+    fn matches(query: &str, item: &TodoItem) -> bool {
+        Query::parse(query, today())
+            .unwrap()
+            .get_match(item, false)
+            .is_some()
+    }
+
+    #[test]
+    fn it_parses_queries_into_ast() {
+        assert_eq!(
+            Query::parse("#work -is:done OR \"Weekly report\"", today()),
+            Ok(Query::Or(vec![
+                Query::And(vec![
+                    Query::Tag("work".to_string()),
+                    Query::Not(Box::new(Query::IsDone(true))),
+                ]),
+                Query::Text("weekly report".to_string()),
+            ]))
+        );
+        assert_eq!(
+            Query::parse("due:<today", today()),
+            Ok(Query::Due(Comparison::Less, today()))
+        );
+        assert_eq!(Query::parse("", today()), Ok(Query::And(vec![])));
+    }
+
+    #[test]
+    fn it_matches_items() {
+        let mut item = create_item("Send weekly report", &["work"]);
+        item.due = Some(DueDate {
+            date: today().pred(),
+            time: None,
+        });
+
+        assert!(matches("#work -#blocked", &item));
+        assert!(!matches("#wor", &item));
+        assert!(matches("report weekly", &item));
+        assert!(!matches("\"report weekly\"", &item));
+        assert!(matches("is:open due:<today", &item));
+        assert!(!matches("is:done OR due:>=today", &item));
+        assert!(matches("(is:done OR #work) created:>2024-01-01", &item));
+        assert!(!matches("created:<2024-01-01", &item));
+        assert!(matches("", &item));
+    }
+
+    #[test]
+    fn it_returns_matched_positions() {
+        let item = create_item("Send weekly report", &["work"]);
+        let get_match = |query: &str, is_fuzzy: bool| {
+            Query::parse(query, today())
+                .unwrap()
+                .get_match(&item, is_fuzzy)
+                .map(|query_match| query_match.positions.into_iter().collect::<Vec<usize>>())
+        };
+
+        assert_eq!(get_match("WEEK #work", false), Some(vec![5, 6, 7, 8]));
+        assert_eq!(get_match("sdwr", false), None);
+        assert_eq!(get_match("sdwr", true), Some(vec![0, 3, 5, 12]));
+        assert_eq!(get_match("sdwr -sdwr", true), None);
+        assert_eq!(get_match("sdwr OR xyz", true), Some(vec![0, 3, 5, 12]));
+    }
+
+    #[test]
+    fn it_reports_parse_errors() {
+        for query in &[
+            "\"weekly",
+            "is:later",
+            "due:<someday",
+            "#work OR",
+            "OR #work",
+            "(#work",
+            "#work)",
+            "#work -)",
+        ] {
+            assert!(Query::parse(query, today()).is_err(), "{}", query);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+// Scores are similar to fzf: every matched character scores, matches at word starts and
+// right after the previous match score more, and skipped characters cost a bit.
+const SCORE_MATCH: i64 = 16;
+const BONUS_WORD_START: i64 = 8;
+const BONUS_CONSECUTIVE: i64 = 12;
+const PENALTY_GAP_START: i64 = 3;
+const PENALTY_GAP_EXTENSION: i64 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    // Indexes of the matched characters in the text
+    pub positions: Vec<usize>,
+}
+
+/// Matches the pattern as a case-insensitive subsequence of the text and returns the best
+/// scoring match, e.g. `wkrp` matches `weekly report`.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<FuzzyMatch> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    if pattern.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: vec![],
+        });
+    }
+
+    // scores[i][j] is the best score of matching pattern[..=i] with pattern[i] at text[j],
+    // previous[i][j] is where pattern[i - 1] is matched in that case
+    let mut scores: Vec<Vec<Option<i64>>> = vec![vec![None; text.len()]; pattern.len()];
+    let mut previous = vec![vec![0; text.len()]; pattern.len()];
+
+    for (i, &pattern_char) in pattern.iter().enumerate() {
+        for (j, &text_char) in text.iter().enumerate() {
+            if !chars_equal(pattern_char, text_char) {
+                continue;
+            }
+
+            let bonus = if j == 0 || !text[j - 1].is_alphanumeric() {
+                BONUS_WORD_START
+            } else {
+                0
+            };
+
+            if i == 0 {
+                scores[i][j] = Some(SCORE_MATCH + bonus);
+                continue;
+            }
+
+            for k in 0..j {
+                let previous_score = match scores[i - 1][k] {
+                    Some(score) => score,
+                    None => continue,
+                };
+                let gap = (j - k - 1) as i64;
+                let transition = if gap == 0 {
+                    BONUS_CONSECUTIVE
+                } else {
+                    -PENALTY_GAP_START - (gap - 1) * PENALTY_GAP_EXTENSION
+                };
+                let score = previous_score + SCORE_MATCH + bonus + transition;
+
+                if scores[i][j].is_none_or(|best| score > best) {
+                    scores[i][j] = Some(score);
+                    previous[i][j] = k;
+                }
+            }
+        }
+    }
+
+    let last = pattern.len() - 1;
+    let (mut position, score) = scores[last]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, score)| score.map(|score| (j, score)))
+        .max_by_key(|&(j, score)| (score, std::cmp::Reverse(j)))?;
+
+    let mut positions = vec![position];
+    for i in (1..=last).rev() {
+        position = previous[i][position];
+        positions.push(position);
+    }
+    positions.reverse();
+
+    Some(FuzzyMatch { score, positions })
+}
+
+/// Compares characters ignoring case.
+pub fn chars_equal(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_matches_subsequences() {
+        assert_eq!(
+            fuzzy_match("wkrp", "Weekly report").unwrap().positions,
+            vec![0, 3, 7, 9]
+        );
+        assert_eq!(fuzzy_match("", "anything").unwrap().score, 0);
+        assert_eq!(fuzzy_match("rw", "weekly report"), None);
+        assert_eq!(fuzzy_match("abc", "ab"), None);
+    }
+
+    #[test]
+    fn it_prefers_word_starts_and_consecutive_matches() {
+        // `rep` is matched as a whole word rather than spread over the text
+        assert_eq!(
+            fuzzy_match("rep", "prepare report").unwrap().positions,
+            vec![8, 9, 10]
+        );
+
+        let consecutive = fuzzy_match("bill", "pay bills").unwrap().score;
+        let spread = fuzzy_match("bill", "buy ice and lollies").unwrap().score;
+        assert!(consecutive > spread);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::todo_item::TodoItem;
+
+// Older changes are forgotten
+const HISTORY_LIMIT: usize = 100;
+
+// Change to the list together with the items as they were on the other side of it
+struct Change {
+    description: String,
+    items: Vec<TodoItem>,
+}
+
+/// Undo and redo stacks of the changes made to the open list.
+#[derive(Default)]
+pub struct History {
+    undo_stack: Vec<Change>,
+    redo_stack: Vec<Change>,
+}
+
+impl History {
+    /// Remembers the items before a change, e.g. `remove "fix CI"`. Undone changes cannot be
+    /// redone after a new one.
+    pub fn record(&mut self, description: String, items: Vec<TodoItem>) {
+        self.undo_stack.push(Change { description, items });
+        if self.undo_stack.len() > HISTORY_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+    }
+
+    /// Returns the description of the latest change and the items before it.
+    /// Current `items` are kept to redo the change.
+    pub fn undo(&mut self, items: Vec<TodoItem>) -> Option<(String, Vec<TodoItem>)> {
+        let change = self.undo_stack.pop()?;
+        self.redo_stack.push(Change {
+            description: change.description.clone(),
+            items,
+        });
+
+        Some((change.description, change.items))
+    }
+
+    /// Returns the description of the latest undone change and the items after it.
+    pub fn redo(&mut self, items: Vec<TodoItem>) -> Option<(String, Vec<TodoItem>)> {
+        let change = self.redo_stack.pop()?;
+        self.undo_stack.push(Change {
+            description: change.description.clone(),
+            items,
+        });
+
+        Some((change.description, change.items))
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_names(items: &[TodoItem]) -> Vec<&str> {
+        items.iter().map(|item| item.name.as_str()).collect()
+    }
+
+    #[test]
+    fn it_undoes_and_redoes_changes() {
+        let mut history = History::default();
+        let before = vec![TodoItem::new("A")];
+        let after = vec![TodoItem::new("A"), TodoItem::new("B")];
+
+        history.record("add \"B\"".to_string(), before);
+        let (description, items) = history.undo(after).unwrap();
+        assert_eq!(description, "add \"B\"");
+        assert_eq!(get_names(&items), vec!["A"]);
+        assert!(history.undo(items.clone()).is_none());
+
+        let (_, items) = history.redo(items).unwrap();
+        assert_eq!(get_names(&items), vec!["A", "B"]);
+        assert!(history.redo(items).is_none());
+    }
+
+    #[test]
+    fn it_forgets_redo_after_new_change() {
+        let mut history = History::default();
+        history.record("add \"A\"".to_string(), vec![]);
+        history.undo(vec![TodoItem::new("A")]).unwrap();
+
+        history.record("add \"B\"".to_string(), vec![]);
+        assert!(history.redo(vec![TodoItem::new("B")]).is_none());
+
+        for _ in 0..HISTORY_LIMIT + 1 {
+            history.record("add \"C\"".to_string(), vec![]);
+        }
+        assert_eq!(history.undo_stack.len(), HISTORY_LIMIT);
+    }
+}
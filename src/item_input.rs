@@ -0,0 +1,195 @@
+use std::collections::BTreeSet;
+
+use chrono::NaiveDate;
+
+use crate::due_date::DueDate;
+use crate::priority::Priority;
+use crate::recurrence::Recurrence;
+use crate::todo_item::TodoItem;
+
+// Starts a due date in the task input, e.g. `pay invoice @fri 17:00`
+pub const DUE_DATE_PREFIX: char = '@';
+// Starts a tag, e.g. `#work`
+pub const TAG_PREFIX: char = '#';
+// Starts a recurrence rule, e.g. `*weekly`
+pub const RECURRENCE_PREFIX: char = '*';
+
+/// Task fields entered in the create and edit prompts.
+#[derive(Debug, PartialEq)]
+pub struct ItemInput {
+    pub name: String,
+    pub due: Option<DueDate>,
+    pub priority: Priority,
+    pub tags: BTreeSet<String>,
+    pub recurrence: Option<Recurrence>,
+}
+
+impl ItemInput {
+    /// Splits the input into the task name and its attributes. Words which cannot be parsed
+    /// as attributes, e.g. `@someday`, stay in the name.
+    pub fn parse(input: &str, today: NaiveDate) -> ItemInput {
+        let words: Vec<&str> = input.split_whitespace().collect();
+        let mut name_words = vec![];
+        let mut due = None;
+        let mut priority = None;
+        let mut tags = BTreeSet::new();
+        let mut recurrence = None;
+
+        let mut index = 0;
+        while index < words.len() {
+            let word = words[index];
+
+            if due.is_none() && word.starts_with(DUE_DATE_PREFIX) {
+                let mut due_words = words[index..].to_vec();
+                due_words[0] = &word[DUE_DATE_PREFIX.len_utf8()..];
+
+                if let Some((due_date, word_count)) = DueDate::parse(&due_words, today) {
+                    due = Some(due_date);
+                    index += word_count;
+                    continue;
+                }
+            }
+
+            if recurrence.is_none() {
+                if let Some(rule) = word
+                    .strip_prefix(RECURRENCE_PREFIX)
+                    .and_then(Recurrence::parse)
+                {
+                    recurrence = Some(rule);
+                    index += 1;
+                    continue;
+                }
+            }
+
+            if let Some(tag) = parse_tag(word) {
+                tags.insert(tag);
+                index += 1;
+                continue;
+            }
+
+            if priority.is_none() {
+                if let Some(word_priority) = Priority::from_marker(word) {
+                    priority = Some(word_priority);
+                    index += 1;
+                    continue;
+                }
+            }
+
+            name_words.push(word);
+            index += 1;
+        }
+
+        ItemInput {
+            name: name_words.join(" "),
+            due,
+            priority: priority.unwrap_or_default(),
+            tags,
+            recurrence,
+        }
+    }
+
+    pub fn into_item(self) -> TodoItem {
+        let mut item = TodoItem::new(&self.name);
+        item.due = self.due;
+        item.priority = self.priority;
+        item.tags = self.tags;
+        item.recurrence = self.recurrence;
+        item
+    }
+
+    pub fn apply_to(self, item: &mut TodoItem) {
+        item.set_name(&self.name);
+        item.set_due(self.due);
+        item.set_priority(self.priority);
+        item.set_tags(self.tags);
+        item.set_recurrence(self.recurrence);
+    }
+
+    /// Renames the task, its attributes are replaced only by the ones written in the input.
+    pub fn rename(self, item: &mut TodoItem) {
+        item.set_name(&self.name);
+        if self.due.is_some() {
+            item.set_due(self.due);
+        }
+        if self.priority != Priority::None {
+            item.set_priority(self.priority);
+        }
+        if !self.tags.is_empty() {
+            item.set_tags(self.tags);
+        }
+        if self.recurrence.is_some() {
+            item.set_recurrence(self.recurrence);
+        }
+    }
+}
+
+/// Returns the lowercase tag if the word is a tag, e.g. `#Work` is `work`. Numbers such as
+/// `#123` are issue references, not tags.
+pub fn parse_tag(word: &str) -> Option<String> {
+    let tag = word.strip_prefix(TAG_PREFIX)?;
+    let is_valid = tag
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        && !tag.chars().all(|c| c.is_ascii_digit());
+
+    if is_valid {
+        Some(tag.to_lowercase())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveTime;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd(2024, 5, 1)
+    }
+
+    #[test]
+    fn it_parses_name_and_due_date() {
+        let input = ItemInput::parse("pay  invoice @fri 17:00 to Bob !! #Work #bills", today());
+
+        assert_eq!(input.name, "pay invoice to Bob");
+        assert_eq!(
+            input.tags.into_iter().collect::<Vec<String>>(),
+            vec!["bills", "work"]
+        );
+        assert_eq!(input.priority, Priority::Medium);
+        assert_eq!(
+            input.due,
+            Some(DueDate {
+                date: NaiveDate::from_ymd(2024, 5, 3),
+                time: Some(NaiveTime::from_hms(17, 0, 0)),
+            })
+        );
+    }
+
+    #[test]
+    fn it_keeps_unknown_attributes_in_name() {
+        let input = ItemInput::parse("ask @bob about it! ! !!!! #1 # #a.b #v2", today());
+
+        assert_eq!(input.name, "ask @bob about it! !!!! #1 # #a.b");
+        assert_eq!(input.tags, ["v2".to_string()].into());
+        assert_eq!(input.due, None);
+        assert_eq!(input.priority, Priority::Low);
+    }
+
+    #[test]
+    fn it_formats_item_as_input() {
+        let item =
+            ItemInput::parse("!!! pay invoice *monthly @tomorrow #bills", today()).into_item();
+
+        assert_eq!(
+            item.to_input_string(),
+            "pay invoice #bills !!! @2024-05-02 *monthly"
+        );
+        let input = ItemInput::parse(&item.to_input_string(), today());
+        assert_eq!(input.due, item.due);
+        assert_eq!(input.priority, item.priority);
+        assert_eq!(input.tags, item.tags);
+        assert_eq!(input.recurrence, item.recurrence);
+    }
+}
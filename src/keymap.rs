@@ -0,0 +1,692 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use termion::event::Key;
+
+/// Something done in the task list on a key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    NewTask,
+    NewSubtask,
+    Filter,
+    Edit,
+    Remove,
+    ToggleDone,
+    Sort,
+    RaisePriority,
+    LowerPriority,
+    MoveDown,
+    MoveUp,
+    Lists,
+    Details,
+    Notes,
+    Undo,
+    Redo,
+    ArchiveDone,
+    Archive,
+    Mark,
+    MarkRange,
+    Unmark,
+    Tag,
+    MoveToList,
+    SelectNext,
+    SelectPrevious,
+    // With a count both select the task with that number
+    SelectFirst,
+    SelectLast,
+    PageDown,
+    PageUp,
+    HalfPageDown,
+    HalfPageUp,
+    Expand,
+    Collapse,
+}
+
+// Names used in the config and descriptions shown in the help
+const ACTIONS: [(Action, &str, &str); 34] = [
+    (Action::Quit, "quit", "quit"),
+    (Action::NewTask, "new_task", "new task"),
+    (Action::NewSubtask, "new_subtask", "new subtask"),
+    (Action::Filter, "filter", "filter tasks"),
+    (Action::Edit, "edit", "edit"),
+    (Action::Remove, "remove", "remove"),
+    (Action::ToggleDone, "toggle_done", "done"),
+    (Action::Sort, "sort", "sort"),
+    (Action::RaisePriority, "raise_priority", "raise priority"),
+    (Action::LowerPriority, "lower_priority", "lower priority"),
+    (Action::MoveDown, "move_down", "move down"),
+    (Action::MoveUp, "move_up", "move up"),
+    (Action::Lists, "lists", "lists"),
+    (Action::Details, "details", "details"),
+    (Action::Notes, "notes", "notes"),
+    (Action::Undo, "undo", "undo"),
+    (Action::Redo, "redo", "redo"),
+    (Action::ArchiveDone, "archive_done", "archive done"),
+    (Action::Archive, "archive", "archive"),
+    (Action::Mark, "mark", "mark"),
+    (Action::MarkRange, "mark_range", "mark range"),
+    (Action::Unmark, "unmark", "unmark"),
+    (Action::Tag, "tag", "tag"),
+    (Action::MoveToList, "move_to_list", "move to list"),
+    (Action::SelectNext, "select_next", "next"),
+    (Action::SelectPrevious, "select_previous", "previous"),
+    (Action::SelectFirst, "select_first", "first"),
+    (Action::SelectLast, "select_last", "last"),
+    (Action::PageDown, "page_down", "page down"),
+    (Action::PageUp, "page_up", "page up"),
+    (Action::HalfPageDown, "half_page_down", "half page down"),
+    (Action::HalfPageUp, "half_page_up", "half page up"),
+    (Action::Expand, "expand", "expand"),
+    (Action::Collapse, "collapse", "collapse"),
+];
+
+// Maps keys to nothing in the config, which removes their default binding
+const NO_ACTION: &str = "none";
+
+impl Action {
+    pub fn get_name(self) -> &'static str {
+        ACTIONS
+            .iter()
+            .find(|(action, _, _)| *action == self)
+            .map_or("", |(_, name, _)| name)
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS
+            .iter()
+            .find(|(_, action_name, _)| *action_name == name)
+            .map(|(action, _, _)| *action)
+    }
+
+    fn get_description(self, is_archive_view: bool) -> &'static str {
+        if self == Action::Archive && is_archive_view {
+            return "back to tasks";
+        }
+
+        ACTIONS
+            .iter()
+            .find(|(action, _, _)| *action == self)
+            .map_or("", |(_, _, description)| description)
+    }
+
+    /// Actions which do not change tasks, the only ones available in the archive view.
+    pub fn is_read_only(self) -> bool {
+        !matches!(
+            self,
+            Action::NewTask
+                | Action::NewSubtask
+                | Action::Edit
+                | Action::Remove
+                | Action::ToggleDone
+                | Action::Sort
+                | Action::RaisePriority
+                | Action::LowerPriority
+                | Action::MoveDown
+                | Action::MoveUp
+                | Action::Lists
+                | Action::Notes
+                | Action::Undo
+                | Action::Redo
+                | Action::ArchiveDone
+                | Action::Mark
+                | Action::MarkRange
+                | Action::Unmark
+                | Action::Tag
+                | Action::MoveToList
+        )
+    }
+}
+
+// Keys are written the way `parse_keys` reads them, the first one is shown in the help
+const DEFAULT_BINDINGS: [(&str, Action); 42] = [
+    ("q", Action::Quit),
+    ("n", Action::NewTask),
+    ("a", Action::NewSubtask),
+    ("f", Action::Filter),
+    ("e", Action::Edit),
+    ("d", Action::Remove),
+    ("backspace", Action::Remove),
+    ("space", Action::ToggleDone),
+    ("enter", Action::ToggleDone),
+    ("s", Action::Sort),
+    ("+", Action::RaisePriority),
+    ("-", Action::LowerPriority),
+    ("J", Action::MoveDown),
+    ("K", Action::MoveUp),
+    ("l", Action::Lists),
+    ("i", Action::Details),
+    ("N", Action::Notes),
+    ("u", Action::Undo),
+    ("ctrl-r", Action::Redo),
+    ("A", Action::ArchiveDone),
+    ("tab", Action::Archive),
+    ("m", Action::Mark),
+    ("v", Action::MarkRange),
+    ("esc", Action::Unmark),
+    ("t", Action::Tag),
+    ("M", Action::MoveToList),
+    ("j", Action::SelectNext),
+    ("down", Action::SelectNext),
+    ("k", Action::SelectPrevious),
+    ("up", Action::SelectPrevious),
+    ("g g", Action::SelectFirst),
+    ("home", Action::SelectFirst),
+    ("G", Action::SelectLast),
+    ("end", Action::SelectLast),
+    ("pagedown", Action::PageDown),
+    ("ctrl-f", Action::PageDown),
+    ("pageup", Action::PageUp),
+    ("ctrl-b", Action::PageUp),
+    ("ctrl-d", Action::HalfPageDown),
+    ("ctrl-u", Action::HalfPageUp),
+    ("right", Action::Expand),
+    ("left", Action::Collapse),
+];
+
+// Order of the help, actions of a group share an entry like `j/k - next/previous`
+const HELP_GROUPS: [&[Action]; 25] = [
+    &[Action::Quit],
+    &[Action::SelectNext, Action::SelectPrevious],
+    &[Action::SelectFirst, Action::SelectLast],
+    &[Action::HalfPageDown, Action::HalfPageUp],
+    &[Action::PageDown, Action::PageUp],
+    &[Action::Sort],
+    &[Action::NewTask],
+    &[Action::NewSubtask],
+    &[Action::Edit],
+    &[Action::ToggleDone],
+    &[Action::Remove],
+    &[Action::Filter],
+    &[Action::Lists],
+    &[Action::RaisePriority, Action::LowerPriority],
+    &[Action::MoveDown, Action::MoveUp],
+    &[Action::Mark, Action::MarkRange],
+    &[Action::Unmark],
+    &[Action::Tag],
+    &[Action::MoveToList],
+    &[Action::Collapse, Action::Expand],
+    &[Action::Details],
+    &[Action::Notes],
+    &[Action::Undo, Action::Redo],
+    &[Action::ArchiveDone],
+    &[Action::Archive],
+];
+
+/// Parses keys written like `j`, `ctrl-d` or `pagedown`. Keys of a sequence are separated
+/// with spaces, e.g. `g g`, so a misspelled key name is not taken for a sequence.
+pub fn parse_keys(text: &str) -> Option<Vec<Key>> {
+    let keys = text
+        .split_whitespace()
+        .map(parse_key)
+        .collect::<Option<Vec<Key>>>()?;
+
+    if keys.is_empty() {
+        None
+    } else {
+        Some(keys)
+    }
+}
+
+fn parse_key(word: &str) -> Option<Key> {
+    if let Some(c) = word.strip_prefix("ctrl-").and_then(get_single_char) {
+        return Some(Key::Ctrl(c));
+    }
+    if let Some(c) = word.strip_prefix("alt-").and_then(get_single_char) {
+        return Some(Key::Alt(c));
+    }
+
+    Some(match word.to_lowercase().as_str() {
+        "space" => Key::Char(' '),
+        "enter" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "esc" => Key::Esc,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "home" => Key::Home,
+        "end" => Key::End,
+        _ => return get_single_char(word).map(Key::Char),
+    })
+}
+
+fn get_single_char(word: &str) -> Option<char> {
+    let mut chars = word.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// Formats keys the way the help shows them, e.g. `gg`, `Ctrl-R` or `PgDn`.
+pub fn format_keys(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|key| match key {
+            Key::Char(' ') => "Space".to_string(),
+            Key::Char('\n') => "Enter".to_string(),
+            Key::Char('\t') => "Tab".to_string(),
+            Key::Char(c) => c.to_string(),
+            Key::Ctrl(c) => format!("Ctrl-{}", c.to_ascii_uppercase()),
+            Key::Alt(c) => format!("Alt-{}", c),
+            Key::Esc => "Esc".to_string(),
+            Key::Backspace => "Backspace".to_string(),
+            Key::Delete => "Del".to_string(),
+            Key::Up => "↑".to_string(),
+            Key::Down => "↓".to_string(),
+            Key::Left => "←".to_string(),
+            Key::Right => "→".to_string(),
+            Key::PageUp => "PgUp".to_string(),
+            Key::PageDown => "PgDn".to_string(),
+            Key::Home => "Home".to_string(),
+            Key::End => "End".to_string(),
+            key => format!("{:?}", key),
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq)]
+pub enum KeymapError {
+    InvalidKey(String),
+    // Keys and the name they are mapped to
+    UnknownAction(String, String),
+    // Keys written twice, e.g. as `Enter` and `enter`
+    SameKeys(String, String),
+    // Keys and their action, then the longer keys starting with them which could never be typed
+    Conflict(String, Action, String, Action),
+}
+
+impl Display for KeymapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeymapError::InvalidKey(keys) => write!(
+                f,
+                "`{}` is not a key, keys of a sequence are separated with spaces, e.g. `g g`",
+                keys
+            ),
+            KeymapError::UnknownAction(keys, name) => {
+                let names: Vec<&str> = ACTIONS.iter().map(|(_, name, _)| *name).collect();
+                write!(
+                    f,
+                    "unknown action `{}` for `{}`, expected `{}` or one of: {}",
+                    name,
+                    keys,
+                    NO_ACTION,
+                    names.join(", ")
+                )
+            }
+            KeymapError::SameKeys(keys, other_keys) => {
+                write!(f, "`{}` and `{}` are the same keys", keys, other_keys)
+            }
+            KeymapError::Conflict(keys, action, other_keys, other_action) => write!(
+                f,
+                "`{}` ({}) hides `{}` ({}), map one of them to `{}`",
+                keys,
+                action.get_name(),
+                other_keys,
+                other_action.get_name(),
+                NO_ACTION
+            ),
+        }
+    }
+}
+
+/// Resolves key presses into actions. Keys typed before an action can be a count, e.g. `5j`.
+pub struct Keymap {
+    // Keys mapped in the config come first, so they are shown in the help
+    bindings: Vec<(Vec<Key>, Action)>,
+    // Keys of a sequence like `gg` typed so far
+    pending_keys: Vec<Key>,
+    count: Option<usize>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::from_config(&BTreeMap::new()).expect("invalid default keymap")
+    }
+}
+
+impl Keymap {
+    /// Builds the keymap from the `[keymap]` config section, which maps keys to action names.
+    /// Mapped keys replace their default binding, mapping them to `none` removes it.
+    pub fn from_config(config: &BTreeMap<String, String>) -> Result<Keymap, KeymapError> {
+        let mut mapped_keys: Vec<(&str, Vec<Key>)> = vec![];
+        // Keys are kept as they are written to report conflicts
+        let mut bindings: Vec<(&str, Vec<Key>, Action)> = vec![];
+        for (text, name) in config {
+            let keys = parse_keys(text).ok_or_else(|| KeymapError::InvalidKey(text.clone()))?;
+            if let Some((other_text, _)) = mapped_keys.iter().find(|(_, other)| *other == keys) {
+                return Err(KeymapError::SameKeys(other_text.to_string(), text.clone()));
+            }
+            mapped_keys.push((text, keys.clone()));
+
+            if name != NO_ACTION {
+                let action = Action::from_name(name)
+                    .ok_or_else(|| KeymapError::UnknownAction(text.clone(), name.clone()))?;
+                bindings.push((text, keys, action));
+            }
+        }
+
+        for (text, action) in DEFAULT_BINDINGS.iter() {
+            let keys = parse_keys(text).expect("invalid default key");
+            if mapped_keys.iter().all(|(_, mapped)| *mapped != keys) {
+                bindings.push((text, keys, *action));
+            }
+        }
+
+        for (text, keys, action) in &bindings {
+            let hidden = bindings
+                .iter()
+                .find(|(_, other, _)| other.len() > keys.len() && other.starts_with(keys));
+            if let Some((other_text, _, other_action)) = hidden {
+                return Err(KeymapError::Conflict(
+                    text.to_string(),
+                    *action,
+                    other_text.to_string(),
+                    *other_action,
+                ));
+            }
+        }
+
+        Ok(Keymap {
+            bindings: bindings
+                .into_iter()
+                .map(|(_, keys, action)| (keys, action))
+                .collect(),
+            pending_keys: vec![],
+            count: None,
+        })
+    }
+
+    /// Returns the action of the key sequence ended by this key together with the count typed
+    /// before it. Unknown sequences are dropped.
+    pub fn resolve(&mut self, key: Key) -> Option<(Action, Option<usize>)> {
+        if let Key::Char(c) = key {
+            let is_count_digit = c.is_ascii_digit() && (c != '0' || self.count.is_some());
+            if self.pending_keys.is_empty() && is_count_digit && self.get_action(&[key]).is_none() {
+                let digit = c.to_digit(10).unwrap_or_default() as usize;
+                let count = self.count.unwrap_or_default().saturating_mul(10);
+                self.count = Some(count.saturating_add(digit));
+                return None;
+            }
+        }
+
+        self.pending_keys.push(key);
+        if let Some(action) = self.get_action(&self.pending_keys) {
+            self.pending_keys.clear();
+            return Some((action, self.count.take()));
+        }
+
+        let is_prefix = self
+            .bindings
+            .iter()
+            .any(|(keys, _)| keys.starts_with(&self.pending_keys));
+        if !is_prefix {
+            self.pending_keys.clear();
+            self.count = None;
+        }
+
+        None
+    }
+
+    fn get_action(&self, keys: &[Key]) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(bound_keys, _)| bound_keys == keys)
+            .map(|(_, action)| *action)
+    }
+
+    fn get_keys(&self, action: Action) -> Option<&[Key]> {
+        self.bindings
+            .iter()
+            .find(|(_, bound_action)| *bound_action == action)
+            .map(|(keys, _)| keys.as_slice())
+    }
+
+    /// Formats the first keys bound to the action, e.g. `N` for notes.
+    pub fn get_keys_text(&self, action: Action) -> Option<String> {
+        self.get_keys(action).map(format_keys)
+    }
+
+    /// Lists the bound actions with their first keys, e.g. `q - quit, j/k - next/previous`.
+    /// The archive view only lists the actions available there.
+    pub fn get_help(&self, is_archive_view: bool) -> String {
+        let mut entries = vec![];
+        for group in HELP_GROUPS.iter() {
+            let bound: Vec<(&[Key], Action)> = group
+                .iter()
+                .filter(|action| !is_archive_view || action.is_read_only())
+                .filter_map(|action| self.get_keys(*action).map(|keys| (keys, *action)))
+                .collect();
+            if bound.is_empty() {
+                continue;
+            }
+
+            let keys: Vec<String> = bound.iter().map(|(keys, _)| format_keys(keys)).collect();
+            let descriptions: Vec<&str> = bound
+                .iter()
+                .map(|(_, action)| action.get_description(is_archive_view))
+                .collect();
+            entries.push(format!(
+                "{} - {}",
+                keys.join("/"),
+                join_descriptions(&descriptions)
+            ));
+
+            if let Some((keys, Action::SelectLast)) = bound.last() {
+                entries.push(format!("12{} - task 12", format_keys(keys)));
+            }
+        }
+
+        entries.join(", ")
+    }
+}
+
+// Words shared by all descriptions at their start or end are written once,
+// e.g. `move down/up` or `raise/lower priority`
+fn join_descriptions(descriptions: &[&'static str]) -> String {
+    let words: Vec<Vec<&'static str>> = descriptions
+        .iter()
+        .map(|description| description.split(' ').collect())
+        .collect();
+    // Each description keeps at least one word of its own
+    let max_shared = words
+        .iter()
+        .map(Vec::len)
+        .min()
+        .unwrap_or_default()
+        .saturating_sub(1);
+    let get_word = |words: &[&'static str], index: usize, from_end: bool| {
+        if from_end {
+            words[words.len() - 1 - index]
+        } else {
+            words[index]
+        }
+    };
+    let is_shared = |index: usize, from_end: bool| {
+        let word = get_word(&words[0], index, from_end);
+        words
+            .iter()
+            .all(|other| get_word(other, index, from_end) == word)
+    };
+
+    let prefix_len = (0..max_shared)
+        .take_while(|&index| is_shared(index, false))
+        .count();
+    let suffix_len = (0..max_shared - prefix_len)
+        .take_while(|&index| is_shared(index, true))
+        .count();
+
+    let first = &words[0];
+    let own_words: Vec<String> = words
+        .iter()
+        .map(|words| words[prefix_len..words.len() - suffix_len].join(" "))
+        .collect();
+    let parts = [
+        first[..prefix_len].join(" "),
+        own_words.join("/"),
+        first[first.len() - suffix_len..].join(" "),
+    ];
+
+    parts
+        .iter()
+        .filter(|part| !part.is_empty())
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_config(bindings: &[(&str, &str)]) -> BTreeMap<String, String> {
+        bindings
+            .iter()
+            .map(|(keys, name)| (keys.to_string(), name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn it_parses_keys() {
+        assert_eq!(parse_keys("j"), Some(vec![Key::Char('j')]));
+        assert_eq!(
+            parse_keys("g g"),
+            Some(vec![Key::Char('g'), Key::Char('g')])
+        );
+        assert_eq!(parse_keys("ctrl-d"), Some(vec![Key::Ctrl('d')]));
+        assert_eq!(
+            parse_keys("space PageDown"),
+            Some(vec![Key::Char(' '), Key::PageDown])
+        );
+        assert_eq!(parse_keys(" "), None);
+        // Misspelled names are not taken for sequences of their characters
+        for typo in &["gg", "ctlr-d", "pgdn", "ctrl-shift-d", "ctrl-"] {
+            assert_eq!(parse_keys(typo), None);
+        }
+        assert_eq!(format_keys(&parse_keys("ctrl-r tab").unwrap()), "Ctrl-RTab");
+    }
+
+    #[test]
+    fn it_resolves_sequences_and_counts() {
+        let mut keymap = Keymap::default();
+
+        assert_eq!(
+            keymap.resolve(Key::Char('j')),
+            Some((Action::SelectNext, None))
+        );
+        assert_eq!(keymap.resolve(Key::Char('1')), None);
+        assert_eq!(keymap.resolve(Key::Char('0')), None);
+        assert_eq!(
+            keymap.resolve(Key::Char('k')),
+            Some((Action::SelectPrevious, Some(10)))
+        );
+
+        assert_eq!(keymap.resolve(Key::Char('3')), None);
+        assert_eq!(keymap.resolve(Key::Char('g')), None);
+        assert_eq!(
+            keymap.resolve(Key::Char('g')),
+            Some((Action::SelectFirst, Some(3)))
+        );
+
+        // Unknown sequence is dropped together with its count
+        assert_eq!(keymap.resolve(Key::Char('2')), None);
+        assert_eq!(keymap.resolve(Key::Char('g')), None);
+        assert_eq!(keymap.resolve(Key::Char('x')), None);
+        assert_eq!(
+            keymap.resolve(Key::Char('G')),
+            Some((Action::SelectLast, None))
+        );
+    }
+
+    #[test]
+    fn it_applies_config() {
+        let config = create_config(&[
+            ("x", "remove"),
+            ("d", "none"),
+            ("N", "none"),
+            ("ctrl-n", "new_task"),
+        ]);
+        let mut keymap = Keymap::from_config(&config).unwrap();
+
+        assert_eq!(keymap.resolve(Key::Char('x')), Some((Action::Remove, None)));
+        assert_eq!(keymap.resolve(Key::Char('d')), None);
+        assert_eq!(
+            keymap.resolve(Key::Char('n')),
+            Some((Action::NewTask, None))
+        );
+
+        let help = keymap.get_help(false);
+        assert!(help.starts_with("q - quit, j/k - next/previous, gg/G - first/last, 12G"));
+        assert!(help.contains("Ctrl-N - new task, a - new subtask"));
+        assert!(help.contains("x - remove"));
+        assert_eq!(keymap.get_keys_text(Action::Remove), Some("x".to_string()));
+        assert_eq!(keymap.get_keys_text(Action::Notes), None);
+
+        let archive_help = keymap.get_help(true);
+        assert!(!archive_help.contains("remove"));
+        assert!(archive_help.ends_with("Tab - back to tasks"));
+    }
+
+    #[test]
+    fn it_describes_unpaired_actions_in_full() {
+        assert_eq!(join_descriptions(&["move down", "move up"]), "move down/up");
+        assert_eq!(
+            join_descriptions(&["raise priority", "lower priority"]),
+            "raise/lower priority"
+        );
+        assert_eq!(
+            join_descriptions(&["mark", "mark range"]),
+            "mark/mark range"
+        );
+
+        let config = create_config(&[("J", "none"), ("ctrl-b", "none"), ("pageup", "none")]);
+        let help = Keymap::from_config(&config).unwrap().get_help(false);
+        assert!(help.contains("K - move up,"));
+        assert!(help.contains("PgDn - page down,"));
+        assert!(help.contains("Ctrl-D/Ctrl-U - half page down/up"));
+        assert!(help.contains("+/- - raise/lower priority"));
+    }
+
+    #[test]
+    fn it_rejects_invalid_config() {
+        let get_error = |bindings| Keymap::from_config(&create_config(bindings)).err();
+
+        assert_eq!(
+            get_error(&[("", "quit")]),
+            Some(KeymapError::InvalidKey("".to_string()))
+        );
+        assert_eq!(
+            get_error(&[("x", "explode")]),
+            Some(KeymapError::UnknownAction(
+                "x".to_string(),
+                "explode".to_string()
+            ))
+        );
+        assert_eq!(
+            get_error(&[("Enter", "quit"), ("enter", "sort")]),
+            Some(KeymapError::SameKeys(
+                "Enter".to_string(),
+                "enter".to_string()
+            ))
+        );
+        assert_eq!(
+            get_error(&[("g", "quit")]),
+            Some(KeymapError::Conflict(
+                "g".to_string(),
+                Action::Quit,
+                "g g".to_string(),
+                Action::SelectFirst
+            ))
+        );
+        assert_eq!(get_error(&[("g", "quit"), ("g g", "none")]), None);
+        assert_eq!(
+            get_error(&[("ctlr-d", "remove")]),
+            Some(KeymapError::InvalidKey("ctlr-d".to_string()))
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::StorageConfig;
+
+pub const DEFAULT_LIST_NAME: &str = "todos";
+// Project list checked into a repository, either a file or a directory with the default list
+pub const PROJECT_LIST_FILE: &str = ".rudo.json";
+pub const PROJECT_LIST_DIR: &str = ".rudo";
+// Points to a data file, the same way `--file` does
+pub const DATA_FILE_ENV: &str = "RUDO_DATA";
+
+const LIST_EXTENSION: &str = "json";
+// Archive is kept next to the list, e.g. `todos.json.archive`
+const ARCHIVE_SUFFIX: &str = "archive";
+
+/// Task list which is open: a named list from the data directory, a project list
+/// or an arbitrary file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskList {
+    pub name: String,
+    pub path: PathBuf,
+    // Shown in the UI to tell which list is open
+    pub title: String,
+}
+
+impl TaskList {
+    pub fn in_dir(data_dir: &Path, name: &str) -> TaskList {
+        TaskList {
+            name: name.to_string(),
+            path: data_dir.join(format!("{}.{}", name, LIST_EXTENSION)),
+            title: if name == DEFAULT_LIST_NAME {
+                "My tasks".to_string()
+            } else {
+                name.to_string()
+            },
+        }
+    }
+
+    pub fn from_file(path: PathBuf) -> TaskList {
+        let name = path
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| DEFAULT_LIST_NAME.to_string());
+
+        TaskList {
+            title: name.clone(),
+            name,
+            path,
+        }
+    }
+
+    /// File with archived tasks of the list. Like backups, it is named after the list file,
+    /// so `.rudo.json.*` ignores all files kept next to a project list. It does not have
+    /// the list extension, so it is not offered in the list switcher.
+    pub fn get_archive_path(&self) -> PathBuf {
+        let mut file_name = self.path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(format!(".{}", ARCHIVE_SUFFIX));
+
+        self.path.with_file_name(file_name)
+    }
+
+    fn in_project(project_dir: &Path, path: PathBuf) -> TaskList {
+        let name = project_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| DEFAULT_LIST_NAME.to_string());
+
+        TaskList {
+            title: format!("Project: {}", name),
+            name,
+            path,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LocationError {
+    NoHomeDir,
+    InvalidListName(String),
+}
+
+impl Display for LocationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LocationError::NoHomeDir => write!(f, "cannot find home directory"),
+            LocationError::InvalidListName(name) => write!(
+                f,
+                "invalid list name '{}', use letters, digits, '-' and '_'",
+                name
+            ),
+        }
+    }
+}
+
+/// Returns directory which contains named lists: the one from config, `~/.rudo` if it is
+/// already used, or XDG data directory otherwise.
+pub fn get_data_dir(config: &StorageConfig) -> Result<PathBuf, LocationError> {
+    if let Some(data_dir) = &config.data_dir {
+        return expand_home_dir(data_dir);
+    }
+
+    let legacy_dir = get_legacy_data_dir().ok_or(LocationError::NoHomeDir)?;
+    if legacy_dir.exists() {
+        return Ok(legacy_dir);
+    }
+
+    Ok(dirs::data_dir()
+        .ok_or(LocationError::NoHomeDir)?
+        .join("rudo"))
+}
+
+/// Picks the list to open. Explicit `--file` wins over `--list`, which wins over
+/// the `RUDO_DATA` file and the project list, falling back to the default list.
+pub fn resolve_task_list(
+    file: Option<PathBuf>,
+    list: Option<&str>,
+    env_file: Option<PathBuf>,
+    project_list: Option<TaskList>,
+    data_dir: &Path,
+) -> Result<TaskList, LocationError> {
+    if let Some(file) = file {
+        return Ok(TaskList::from_file(file));
+    }
+
+    if let Some(name) = list {
+        validate_list_name(name)?;
+        return Ok(TaskList::in_dir(data_dir, name));
+    }
+
+    match env_file {
+        Some(file) if !file.as_os_str().is_empty() => Ok(TaskList::from_file(file)),
+        _ => Ok(project_list.unwrap_or_else(|| TaskList::in_dir(data_dir, DEFAULT_LIST_NAME))),
+    }
+}
+
+// Data directory used before it could be configured, it looks like a project list in home
+fn get_legacy_data_dir() -> Option<PathBuf> {
+    Some(dirs::home_dir()?.join(PROJECT_LIST_DIR))
+}
+
+/// Looks for a project list in the directory and its parents, the way git finds `.git`.
+/// The data directory and the legacy `~/.rudo` are skipped.
+pub fn find_project_list(current_dir: &Path, data_dir: &Path) -> Option<TaskList> {
+    let legacy_dir = get_legacy_data_dir();
+    let mut skipped_dirs = vec![data_dir];
+    skipped_dirs.extend(legacy_dir.as_deref());
+
+    find_project_list_skipping(current_dir, &skipped_dirs)
+}
+
+fn find_project_list_skipping(current_dir: &Path, skipped_dirs: &[&Path]) -> Option<TaskList> {
+    current_dir.ancestors().find_map(|dir| {
+        let file = dir.join(PROJECT_LIST_FILE);
+        if file.is_file() {
+            return Some(TaskList::in_project(dir, file));
+        }
+
+        let list_dir = dir.join(PROJECT_LIST_DIR);
+        if list_dir.is_dir() && !skipped_dirs.contains(&list_dir.as_path()) {
+            let file = list_dir.join(format!("{}.{}", DEFAULT_LIST_NAME, LIST_EXTENSION));
+            return Some(TaskList::in_project(dir, file));
+        }
+
+        None
+    })
+}
+
+/// Returns sorted names of the lists stored in the data directory, the default list is
+/// always included.
+pub fn get_list_names(data_dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(data_dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension() == Some(LIST_EXTENSION.as_ref()))
+                .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+                .filter(|name| validate_list_name(name).is_ok())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if !names.iter().any(|name| name == DEFAULT_LIST_NAME) {
+        names.push(DEFAULT_LIST_NAME.to_string());
+    }
+    names.sort();
+    names
+}
+
+fn validate_list_name(name: &str) -> Result<(), LocationError> {
+    let is_valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+
+    if is_valid {
+        Ok(())
+    } else {
+        Err(LocationError::InvalidListName(name.to_string()))
+    }
+}
+
+fn expand_home_dir(path: &Path) -> Result<PathBuf, LocationError> {
+    match path.strip_prefix("~") {
+        Ok(relative_path) => Ok(dirs::home_dir()
+            .ok_or(LocationError::NoHomeDir)?
+            .join(relative_path)),
+        Err(_) => Ok(path.to_path_buf()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn it_resolves_task_list_by_precedence() {
+        let data_dir = Path::new("/data");
+        let file = Some(PathBuf::from("/tmp/file.json"));
+        let env_file = Some(PathBuf::from("/tmp/env.json"));
+
+        assert_eq!(
+            resolve_task_list(file.clone(), Some("work"), env_file.clone(), None, data_dir)
+                .unwrap()
+                .path,
+            PathBuf::from("/tmp/file.json")
+        );
+        assert_eq!(
+            resolve_task_list(None, Some("work"), env_file.clone(), None, data_dir).unwrap(),
+            TaskList {
+                name: "work".to_string(),
+                path: PathBuf::from("/data/work.json"),
+                title: "work".to_string(),
+            }
+        );
+        assert_eq!(
+            resolve_task_list(None, None, env_file, None, data_dir)
+                .unwrap()
+                .name,
+            "env"
+        );
+        assert_eq!(
+            resolve_task_list(None, None, None, None, data_dir).unwrap(),
+            TaskList::in_dir(data_dir, "todos")
+        );
+    }
+
+    #[test]
+    fn it_rejects_list_names_escaping_data_dir() {
+        assert_eq!(
+            resolve_task_list(None, Some("../work"), None, None, Path::new("/data")),
+            Err(LocationError::InvalidListName("../work".to_string()))
+        );
+    }
+
+    #[test]
+    fn it_lists_names_of_stored_lists() {
+        let dir = tempdir().unwrap();
+        for file in &[
+            "work.json",
+            "work.json.1",
+            "work.json.archive",
+            "home.json",
+            "notes.txt",
+        ] {
+            fs::write(dir.path().join(file), "").unwrap();
+        }
+
+        assert_eq!(get_list_names(dir.path()), vec!["home", "todos", "work"]);
+        assert_eq!(
+            TaskList::in_dir(dir.path(), "work").get_archive_path(),
+            dir.path().join("work.json.archive")
+        );
+        assert_eq!(
+            TaskList::from_file(PathBuf::from(PROJECT_LIST_FILE)).get_archive_path(),
+            PathBuf::from(".rudo.json.archive")
+        );
+    }
+
+    #[test]
+    fn it_finds_project_list_in_parent_directories() {
+        let dir = tempdir().unwrap();
+        let project_dir = dir.path().join("rudo");
+        let nested_dir = project_dir.join("src").join("app");
+        fs::create_dir_all(&nested_dir).unwrap();
+        let data_dir = Path::new("/data");
+
+        assert_eq!(find_project_list(&nested_dir, data_dir), None);
+
+        fs::create_dir(project_dir.join(PROJECT_LIST_DIR)).unwrap();
+        let project_list = find_project_list(&nested_dir, data_dir).unwrap();
+        assert_eq!(
+            project_list.path,
+            project_dir.join(".rudo").join("todos.json")
+        );
+        assert_eq!(project_list.title, "Project: rudo");
+
+        // Closer file wins, and the data directory is never a project
+        fs::write(nested_dir.join(PROJECT_LIST_FILE), "").unwrap();
+        assert_eq!(
+            find_project_list(&nested_dir, data_dir).unwrap().path,
+            nested_dir.join(".rudo.json")
+        );
+        assert_eq!(
+            find_project_list(&project_dir, &project_dir.join(PROJECT_LIST_DIR)),
+            None
+        );
+    }
+
+    #[test]
+    fn it_skips_legacy_data_dir_with_configured_data_dir() {
+        let home_dir = tempdir().unwrap();
+        let legacy_dir = home_dir.path().join(PROJECT_LIST_DIR);
+        fs::create_dir(&legacy_dir).unwrap();
+        let data_dir = Path::new("/data");
+
+        assert!(find_project_list_skipping(home_dir.path(), &[data_dir]).is_some());
+        assert_eq!(
+            find_project_list_skipping(home_dir.path(), &[data_dir, &legacy_dir]),
+            None
+        );
+    }
+}
@@ -1,187 +1,344 @@
-use std::io::{stdin, stdout, Stdout};
-use std::sync::mpsc::{Receiver, RecvTimeoutError};
-use std::sync::{mpsc, Arc, Mutex};
-use std::{fs, thread};
-use std::{io, process};
-
-use serde::{Deserialize, Serialize};
+use std::io::{stdin, stdout, Stdout, Write};
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::{env, fs, io, process};
+
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use structopt::StructOpt;
 use termion::event::Key;
 use termion::input::TermRead;
 use termion::raw::{IntoRawMode, RawTerminal};
 use tui::backend::TermionBackend;
-use tui::text::{Span, Spans};
-use tui::widgets::ListItem;
 use tui::Terminal;
 
-use crate::app::{App, AppStage};
+use crate::app::{App, AppStage, StatusMessage};
 use crate::app_layout::AppLayout;
+use crate::archive::{archive_old_tasks, archive_tasks, take_done_tasks};
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::keymap::{Action, Keymap};
+use crate::location::{
+    find_project_list, get_data_dir, get_list_names, resolve_task_list, TaskList, DATA_FILE_ENV,
+};
+use crate::storage::{dump, find_latest_backup, get_app_data, quarantine, Data, LoadError};
+use crate::task_tree::take_with_subtasks;
 use crate::todo_item::TodoItem;
-use crate::update::{update, CURRENT_APP_VERSION};
+use crate::update::{get_update_source, spawn_update_check};
+use crate::utils::confirm;
 
 use std::path::PathBuf;
 use std::time::Duration;
 
 mod app;
 mod app_layout;
+mod archive;
+mod cli;
+mod config;
+mod due_date;
+mod filter;
+mod fuzzy;
+mod history;
+mod item_input;
+mod keymap;
+mod location;
+mod priority;
+mod recurrence;
+mod schema;
+mod storage;
+mod task_tree;
+mod text_input;
 mod todo_item;
 mod update;
 mod utils;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Data {
-    items: Vec<TodoItem>,
-}
-
 // How often app updates if key even is not received.
 // Required to maintain proper layout on window size change.
 const APP_TICK_MS: u64 = 100;
 
-fn dump(data: Data) {
-    let (_path_to_file_dir, path_to_file) = get_file_path();
+// Changes are saved once nothing has changed for that long
+const AUTOSAVE_DELAY_MS: u64 = 500;
+
+// Used to edit notes when neither VISUAL nor EDITOR is set
+const DEFAULT_EDITOR: &str = "vi";
+
+// Files behind the running app
+struct Session {
+    data_dir: PathBuf,
+    task_list: TaskList,
+    // Tasks done that many days ago are archived when a list is opened
+    auto_archive_after_days: Option<u32>,
+    keymap: Keymap,
+}
+
+// Terminal the UI is drawn on, raw mode is suspended while an external editor runs
+struct Screen {
+    terminal: Terminal<TermionBackend<Stdout>>,
+    raw_mode: RawTerminal<Stdout>,
+}
 
-    let content = serde_json::to_string(&data).expect("Json serialization failed");
-    fs::write(path_to_file, content).expect("Data cannot be saved");
+// Events from the listener workers. The key listener waits until a key is handled before
+// reading the next one, so it does not take keys typed into an external editor.
+struct TerminalEvents {
+    receiver: Receiver<TerminalEvent>,
+    key_handled: Sender<()>,
 }
 
 enum TerminalEvent {
     Input(Key),
+    // Terminal is closed or the process is asked to stop, app should save and exit
+    Terminate,
 }
 
 fn main() -> Result<(), io::Error> {
-    // Update application to the latest release
-    match update() {
-        Ok(version) => {
-            if version == CURRENT_APP_VERSION {
-                println!("Rudo is up to date!");
-            } else {
-                println!("Successfully updated to version {}", version);
-                process::exit(0);
-            }
+    let cli = Cli::from_args();
+
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("rudo: {}", error);
+            process::exit(1);
         }
-        Err(error) if error.to_string().contains("Update aborted") => {}
+    };
+
+    let keymap = match Keymap::from_config(&config.keymap) {
+        Ok(keymap) => keymap,
+        Err(error) => {
+            eprintln!("rudo: invalid keymap in config: {}", error);
+            process::exit(1);
+        }
+    };
+
+    let Cli {
+        command,
+        file,
+        list,
+    } = cli;
+    let env_file = env::var_os(DATA_FILE_ENV).map(PathBuf::from);
+    let mut session = match get_data_dir(&config.storage).and_then(|data_dir| {
+        let project_list = env::current_dir()
+            .ok()
+            .and_then(|current_dir| find_project_list(&current_dir, &data_dir));
+        let task_list =
+            resolve_task_list(file, list.as_deref(), env_file, project_list, &data_dir)?;
+        Ok(Session {
+            data_dir,
+            task_list,
+            auto_archive_after_days: config.archive.auto_archive_after_days,
+            keymap,
+        })
+    }) {
+        Ok(session) => session,
         Err(error) => {
-            println!("---------------------------------------");
-            println!("Error occurred during update. Please report it here:");
-            println!("https://github.com/GlebIrovich/rudo/issues");
+            eprintln!("rudo: {}", error);
+            process::exit(1);
+        }
+    };
 
-            println!("{}", error);
-            println!("---------------------------------------");
+    if let Some(command) = command {
+        if let Err(error) = cli::run(command, &session.task_list, &config) {
+            eprintln!("rudo: {}", error);
             process::exit(1);
         }
+        return Ok(());
+    }
+
+    // Loaded before entering raw mode, as recovery may need to ask questions
+    let (mut items, mut is_changed) = match load_app_data(&session.task_list) {
+        Some(data) => data,
+        None => process::exit(1),
     };
+    match archive_old_tasks(
+        &session.task_list,
+        &mut items,
+        session.auto_archive_after_days,
+    ) {
+        Ok(count) => is_changed |= count > 0,
+        // Tasks stay in the list, it is not worth to stop the app
+        Err(error) => eprintln!("rudo: {}", error),
+    }
 
-    let stdout = stdout().into_raw_mode()?;
-    let backend = TermionBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    let raw_mode = stdout().into_raw_mode()?;
+    let backend = TermionBackend::new(stdout());
+    let mut screen = Screen {
+        terminal: Terminal::new(backend)?,
+        raw_mode,
+    };
 
     // Application state
-    let mut app = App::new(get_app_data());
+    let mut app = App::new(items);
+    app.cascade_completion = config.tasks.cascade_completion;
+    app.list_name = session.task_list.name.clone();
+    app.list_title = session.task_list.title.clone();
+    if is_changed {
+        app.mark_as_changed();
+    }
 
     // Clean screen
-    terminal.clear().expect("Terminal clean failed");
-
-    let key_events_receiver = spawn_key_event_listener_worker(Arc::clone(&app.stage));
+    screen.terminal.clear().expect("Terminal clean failed");
+
+    let (events_sender, events_receiver) = mpsc::channel();
+    let (key_handled_sender, key_handled_receiver) = mpsc::channel();
+    spawn_key_event_listener_worker(events_sender.clone(), key_handled_receiver);
+    spawn_signal_listener_worker(events_sender)?;
+    let events = TerminalEvents {
+        receiver: events_receiver,
+        key_handled: key_handled_sender,
+    };
+    let update_receiver = if config.update.check_on_startup {
+        Some(spawn_update_check(get_update_source(&config.update.source)))
+    } else {
+        None
+    };
 
     loop {
-        terminal
-            .draw(|frame| {
-                let items: Vec<ListItem> = app
-                    .get_filtered_items()
-                    .iter()
-                    .map(|(index, item)| {
-                        let lines = vec![Spans::from(Span::from(format!(
-                            "{}. [{}] - {}",
-                            index + 1,
-                            if item.completed { 'X' } else { ' ' },
-                            item.name.clone()
-                        )))];
-                        ListItem::new(lines)
-                    })
-                    .collect();
+        if let Some(version) = update_receiver.as_ref().and_then(|r| r.try_recv().ok()) {
+            app.available_update = Some(version);
+        }
+
+        if app.has_settled_changes(Duration::from_millis(AUTOSAVE_DELAY_MS)) {
+            // Failure is shown in the UI and saving is retried after the next delay
+            let _ = save_app_data(&mut app, &session.task_list);
+        }
 
+        screen
+            .terminal
+            .draw(|frame| {
                 let mut app_layout = AppLayout::new();
                 let frame_size = frame.size();
 
                 let (app_chunks, list_chunks) = app_layout.update_layout_chunks(&app, frame_size);
 
-                app_layout.draw_filter_widget(frame, &app.filter_term, app_chunks[0]);
+                app_layout.draw_filter_widget(
+                    frame,
+                    &app.filter_term,
+                    app.get_stage_clone() == AppStage::Filter,
+                    app_chunks[0],
+                );
+                // Borders take two rows
+                app.list_height = list_chunks[0].height.saturating_sub(2) as usize;
                 app_layout.list_layout.draw_list_widget(
                     frame,
-                    items,
+                    &app.get_visible_rows(),
+                    &app.get_marked_ids(),
                     list_chunks[0],
                     &mut app.list.state,
                 );
-                app_layout.draw_help_widget(frame, &*app.stage.lock().unwrap(), app_chunks[2]);
+                app_layout.draw_help_widget(frame, &app, &session.keymap, app_chunks[2]);
 
                 match &*app.stage.lock().unwrap() {
-                    AppStage::CreateItem | AppStage::UpdateItem => {
+                    AppStage::CreateItem
+                    | AppStage::CreateSubItem
+                    | AppStage::UpdateItem
+                    | AppStage::TagItems => {
                         app_layout.list_layout.draw_item_input_widget(
                             frame,
                             &app.item_name_input,
                             list_chunks[1],
                         );
                     }
+                    AppStage::SwitchList | AppStage::MoveToList => {
+                        app_layout.list_layout.draw_list_switcher_widget(
+                            frame,
+                            &app.list_switcher.items,
+                            list_chunks[1],
+                            &mut app.list_switcher.state,
+                        );
+                    }
+                    _ if app.show_details => {
+                        app_layout.list_layout.draw_details_widget(
+                            frame,
+                            app.get_selected_row().as_ref(),
+                            &session.keymap,
+                            list_chunks[1],
+                        );
+                    }
                     _ => (),
                 }
             })
             .expect("Terminal draw failed");
 
-        if let true = key_down_handler(&key_events_receiver, &mut app, &mut terminal) {
+        if key_down_handler(&events, &mut app, &mut session, &mut screen) {
             break Result::Ok(());
         };
     }
 }
 
-fn spawn_key_event_listener_worker(app_stage: Arc<Mutex<AppStage>>) -> Receiver<TerminalEvent> {
+fn spawn_key_event_listener_worker(sender: Sender<TerminalEvent>, key_handled: Receiver<()>) {
     let stdin = stdin();
 
-    let (sender, receiver) = mpsc::channel();
-
     thread::spawn(move || {
         //detecting keydown events
-        for event in stdin.keys() {
-            match event.unwrap() {
-                Key::Char('q') => match *app_stage.lock().unwrap() {
-                    AppStage::CreateItem | AppStage::UpdateItem | AppStage::Filter => {
-                        sender.send(TerminalEvent::Input(Key::Char('q'))).unwrap()
-                    }
-                    _ => {
-                        sender.send(TerminalEvent::Input(Key::Char('q'))).unwrap();
-                        break;
-                    }
-                },
-                key => sender.send(TerminalEvent::Input(key)).unwrap(),
+        for key in stdin.keys() {
+            let key = match key {
+                Ok(key) => key,
+                Err(_) => break,
+            };
+            // Quitting is decided by the main thread, e.g. it stays open if saving fails
+            if sender.send(TerminalEvent::Input(key)).is_err() || key_handled.recv().is_err() {
+                return;
             }
         }
+
+        // Input is gone, e.g. the terminal was closed
+        let _ = sender.send(TerminalEvent::Terminate);
     });
+}
+
+fn spawn_signal_listener_worker(sender: Sender<TerminalEvent>) -> Result<(), io::Error> {
+    let mut signals = Signals::new([SIGTERM, SIGHUP, SIGINT])?;
 
-    receiver
+    thread::spawn(move || {
+        if signals.forever().next().is_some() {
+            let _ = sender.send(TerminalEvent::Terminate);
+        }
+    });
+
+    Ok(())
 }
 
 fn key_down_handler(
-    receiver: &Receiver<TerminalEvent>,
+    events: &TerminalEvents,
     app: &mut App,
-    terminal: &mut Terminal<TermionBackend<RawTerminal<Stdout>>>,
+    session: &mut Session,
+    screen: &mut Screen,
 ) -> bool {
-    match receiver.recv_timeout(Duration::from_millis(APP_TICK_MS)) {
-        Result::Ok(event) => key_action_mapper(event, app, terminal),
-        Err(RecvTimeoutError::Timeout) => {
-            return false;
-        }
-        Err(_) => {
-            return true;
+    match events
+        .receiver
+        .recv_timeout(Duration::from_millis(APP_TICK_MS))
+    {
+        Result::Ok(event) => {
+            let is_key = matches!(event, TerminalEvent::Input(_));
+            let should_quit = key_action_mapper(event, app, session, screen);
+            if is_key {
+                let _ = events.key_handled.send(());
+            }
+            should_quit
         }
-    };
-
-    false
+        Err(RecvTimeoutError::Timeout) => false,
+        Err(_) => true,
+    }
 }
 
 fn key_action_mapper(
     event: TerminalEvent,
     app: &mut App,
-    terminal: &mut Terminal<TermionBackend<RawTerminal<Stdout>>>,
+    session: &mut Session,
+    screen: &mut Screen,
 ) -> bool {
+    let previous_status_message = app.status_message.take();
+    // Second attempt to quit after failed save quits without saving
+    let force_quit = matches!(previous_status_message, Some(StatusMessage::SaveError(_)));
+
     match event {
+        TerminalEvent::Terminate => return quit(app, session, screen, true),
+        TerminalEvent::Input(Key::Ctrl('c')) => return quit(app, session, screen, force_quit),
+        TerminalEvent::Input(key) if app.get_stage_clone() == AppStage::Default => {
+            if let Some((action, count)) = session.keymap.resolve(key) {
+                return perform_action(action, count, app, session, screen, force_quit);
+            }
+        }
         TerminalEvent::Input(Key::Char(key)) => match app.get_stage_clone() {
             AppStage::CreateItem => match key {
                 '\n' => {
@@ -189,7 +346,19 @@ fn key_action_mapper(
                     app.reset_item_name_input();
                     app.set_stage(AppStage::Default);
                 }
-                key => app.item_input_add_character(key),
+                key => {
+                    app.item_name_input.handle_key(Key::Char(key));
+                }
+            },
+            AppStage::CreateSubItem => match key {
+                '\n' => {
+                    app.add_new_subitem();
+                    app.reset_item_name_input();
+                    app.set_stage(AppStage::Default);
+                }
+                key => {
+                    app.item_name_input.handle_key(Key::Char(key));
+                }
             },
             AppStage::UpdateItem => match key {
                 '\n' => {
@@ -197,77 +366,355 @@ fn key_action_mapper(
                     app.reset_item_name_input();
                     app.set_stage(AppStage::Default);
                 }
-                key => app.item_input_add_character(key),
+                key => {
+                    app.item_name_input.handle_key(Key::Char(key));
+                }
             },
-            AppStage::Filter => match key {
+            AppStage::TagItems => match key {
                 '\n' => {
+                    app.tag_tasks();
+                    app.reset_item_name_input();
                     app.set_stage(AppStage::Default);
                 }
-                key => app.filter_term_add_character(key),
+                key => {
+                    app.item_name_input.handle_key(Key::Char(key));
+                }
             },
-            AppStage::Default => match key {
-                'n' => app.set_stage(AppStage::CreateItem),
-                'f' => app.set_stage(AppStage::Filter),
-                'e' => app.set_stage(AppStage::UpdateItem),
-                'd' => app.remove_task(),
-                ' ' | '\n' => app.toggle_task(),
-                's' => app.toggle_sorting(),
-                'q' => {
-                    terminal.clear().unwrap();
-                    dump(Data {
-                        items: app.list.items.clone(),
-                    });
-                    return true;
+            AppStage::Filter => match key {
+                '\n' => {
+                    app.filter_term.add_to_history();
+                    app.set_stage(AppStage::Default);
+                }
+                '\t' => app.toggle_fuzzy_filter(),
+                key => {
+                    app.filter_term.handle_key(Key::Char(key));
                 }
-                _ => (),
             },
+            AppStage::SwitchList => {
+                if key == '\n' {
+                    switch_list(app, session);
+                }
+            }
+            AppStage::MoveToList => {
+                if key == '\n' {
+                    move_to_list(app, session);
+                }
+            }
+            // Handled through the keymap
+            AppStage::Default => (),
         },
         TerminalEvent::Input(special_key) => match app.get_stage_clone() {
-            AppStage::CreateItem | AppStage::UpdateItem => {
-                if let Key::Backspace = special_key {
-                    app.item_input_remove_character()
-                }
+            AppStage::CreateItem | AppStage::CreateSubItem | AppStage::UpdateItem => {
+                app.item_name_input.handle_key(special_key);
             }
-            AppStage::Filter => {
-                if let Key::Backspace = special_key {
-                    app.filter_term_remove_character()
+            AppStage::TagItems => match special_key {
+                Key::Esc => app.set_stage(AppStage::Default),
+                key => {
+                    app.item_name_input.handle_key(key);
                 }
+            },
+            AppStage::Filter => {
+                app.filter_term.handle_key(special_key);
             }
-            AppStage::Default => match special_key {
-                Key::Backspace => app.remove_task(),
-                Key::Down => app.list.next(),
-                Key::Up => app.list.previous(),
+            AppStage::SwitchList | AppStage::MoveToList => match special_key {
+                Key::Esc => app.set_stage(AppStage::Default),
+                Key::Down => app.list_switcher.next(),
+                Key::Up => app.list_switcher.previous(),
                 _ => (),
             },
+            // Handled through the keymap
+            AppStage::Default => (),
         },
     };
 
     false
 }
 
-fn get_app_data() -> Vec<TodoItem> {
-    let (path_to_file_dir, path_to_file) = get_file_path();
+/// Performs an action of the task list, `count` is the number typed before its keys.
+/// Returns `true` if the app should quit.
+fn perform_action(
+    action: Action,
+    count: Option<usize>,
+    app: &mut App,
+    session: &mut Session,
+    screen: &mut Screen,
+    force_quit: bool,
+) -> bool {
+    // Archive can be only viewed
+    if app.archive.is_some() && !action.is_read_only() {
+        return false;
+    }
+
+    let page_size = app.get_page_size() as isize;
+    let offset = count.unwrap_or(1) as isize;
+    match (action, count) {
+        (Action::Quit, _) => return quit(app, session, screen, force_quit),
+        (Action::NewTask, _) => app.set_stage(AppStage::CreateItem),
+        (Action::NewSubtask, _) => app.set_stage(AppStage::CreateSubItem),
+        (Action::Filter, _) => app.set_stage(AppStage::Filter),
+        (Action::Edit, _) => app.set_stage(AppStage::UpdateItem),
+        (Action::Remove, _) => app.remove_task(),
+        (Action::ToggleDone, _) => app.toggle_task(),
+        (Action::Sort, _) => app.toggle_sorting(),
+        (Action::RaisePriority, _) => app.raise_priority(),
+        (Action::LowerPriority, _) => app.lower_priority(),
+        (Action::MoveDown, _) => app.move_task_down(),
+        (Action::MoveUp, _) => app.move_task_up(),
+        (Action::Lists, _) => app.open_list_switcher(get_list_names(&session.data_dir)),
+        (Action::Details, _) => app.toggle_details(),
+        (Action::Notes, _) => edit_notes(app, screen),
+        (Action::Undo, _) => app.undo(),
+        (Action::Redo, _) => app.redo(),
+        (Action::ArchiveDone, _) => archive_done_tasks(app, session),
+        (Action::Archive, _) if app.archive.is_some() => app.close_archive(),
+        (Action::Archive, _) => open_archive(app, session),
+        (Action::Mark, _) => app.toggle_mark(),
+        (Action::MarkRange, _) => app.toggle_range_selection(),
+        (Action::Unmark, _) => app.clear_marks(),
+        (Action::Tag, _) => app.set_stage(AppStage::TagItems),
+        (Action::MoveToList, _) => app.open_move_to_list(get_list_names(&session.data_dir)),
+        // Single steps wrap around the list, counted ones stop at its ends
+        (Action::SelectNext, None) => app.select_next(),
+        (Action::SelectNext, Some(_)) => app.select_by(offset),
+        (Action::SelectPrevious, None) => app.select_previous(),
+        (Action::SelectPrevious, Some(_)) => app.select_by(-offset),
+        (Action::SelectFirst, None) => app.select_first(),
+        (Action::SelectLast, None) => app.select_last(),
+        (Action::SelectFirst, Some(number)) | (Action::SelectLast, Some(number)) => {
+            app.select_task_number(number)
+        }
+        (Action::PageDown, _) => app.select_by(offset * page_size),
+        (Action::PageUp, _) => app.select_by(-offset * page_size),
+        (Action::HalfPageDown, _) => app.select_by(offset * (page_size / 2).max(1)),
+        (Action::HalfPageUp, _) => app.select_by(-offset * (page_size / 2).max(1)),
+        (Action::Expand, _) => app.expand_task(),
+        (Action::Collapse, _) => app.collapse_task(),
+    }
+
+    false
+}
+
+/// Saves the open list and opens the one selected in the list switcher.
+fn switch_list(app: &mut App, session: &mut Session) {
+    app.set_stage(AppStage::Default);
 
-    match fs::read_dir(&path_to_file_dir) {
-        Ok(_) => {}
-        Err(_) => fs::create_dir_all(path_to_file_dir).unwrap(),
+    let list_name = match app.list_switcher.get_selected_item() {
+        Some(list_name) => list_name,
+        None => return,
+    };
+    let task_list = TaskList::in_dir(&session.data_dir, &list_name);
+    if task_list == session.task_list || save_app_data(app, &session.task_list).is_err() {
+        return;
     }
 
-    match fs::read_to_string(path_to_file) {
-        Ok(data) => {
-            let data: Data = serde_json::from_str(data.as_str()).expect("Parsing json has failed");
-            data.items
+    match get_app_data(&task_list.path) {
+        Ok(mut items) => {
+            let archived =
+                archive_old_tasks(&task_list, &mut items, session.auto_archive_after_days);
+            app.open_list(&task_list, items);
+            session.task_list = task_list;
+            match archived {
+                Ok(0) => {}
+                Ok(_) => app.mark_as_changed(),
+                Err(error) => app.status_message = Some(StatusMessage::Error(error.to_string())),
+            }
         }
-        Err(_) => vec![],
+        Err(error) => app.status_message = Some(StatusMessage::Error(error.to_string())),
     }
 }
 
-fn get_file_path() -> (PathBuf, PathBuf) {
-    let mut path_to_file = dirs::home_dir().unwrap();
-    path_to_file.push(".rudo");
-    let path_to_file_dir = path_to_file.clone();
+/// Moves the marked tasks with their subtasks to the list selected in the list switcher.
+fn move_to_list(app: &mut App, session: &Session) {
+    app.set_stage(AppStage::Default);
 
-    path_to_file.push("todos.json");
+    let list_name = match app.list_switcher.get_selected_item() {
+        Some(list_name) => list_name,
+        None => return,
+    };
+    let task_list = TaskList::in_dir(&session.data_dir, &list_name);
+    let mut items = app.list.items.clone();
+    let mut moved = take_with_subtasks(&mut items, &app.get_target_ids());
+    let moved_count = moved.len();
+    if task_list == session.task_list || moved_count == 0 {
+        return;
+    }
+
+    // Positions belong to the custom order of this list
+    for item in &mut moved {
+        item.position = None;
+    }
+    let result = get_app_data(&task_list.path)
+        .map_err(|error| error.to_string())
+        .and_then(|mut target_items| {
+            target_items.extend(moved);
+            dump(&task_list.path, &Data::new(target_items))
+                .map_err(|error| format!("cannot save {}: {}", list_name, error))
+        });
+    if let Err(error) = result {
+        app.status_message = Some(StatusMessage::Error(error));
+        return;
+    }
 
-    (path_to_file_dir, path_to_file)
+    // Moved tasks are in both lists until the open one is saved too
+    app.apply_move(items, moved_count, &list_name);
+    if let Err(error) = save_app_data(app, &session.task_list) {
+        app.status_message = Some(StatusMessage::Error(format!(
+            "moved tasks are copied to {}, but cannot save {}: {}",
+            list_name, session.task_list.name, error
+        )));
+    }
+}
+
+/// Moves done tasks of the open list to its archive.
+fn archive_done_tasks(app: &mut App, session: &Session) {
+    let mut items = app.list.items.clone();
+    let archived = take_done_tasks(&mut items, None);
+    let archived_count = archived.len();
+    if archived_count == 0 {
+        app.status_message = Some(StatusMessage::Info("No done tasks to archive".to_string()));
+        return;
+    }
+
+    match archive_tasks(&session.task_list, archived) {
+        Ok(()) => app.apply_archive(items, archived_count),
+        Err(error) => app.status_message = Some(StatusMessage::Error(error.to_string())),
+    }
+}
+
+fn open_archive(app: &mut App, session: &Session) {
+    match get_app_data(&session.task_list.get_archive_path()) {
+        Ok(items) => app.open_archive(items),
+        Err(error) => app.status_message = Some(StatusMessage::Error(error.to_string())),
+    }
+}
+
+/// Opens notes of the selected task in `$VISUAL` or `$EDITOR` and keeps them when the editor exits.
+fn edit_notes(app: &mut App, screen: &mut Screen) {
+    let item = match app.get_selected_item() {
+        Some(item) => item,
+        None => return,
+    };
+
+    match run_editor(&item.notes, screen) {
+        // Editors usually end the file with a new line
+        Ok(notes) => app.set_notes(item.id, notes.trim_end()),
+        Err(error) => {
+            app.status_message = Some(StatusMessage::Error(format!(
+                "cannot edit notes: {}",
+                error
+            )))
+        }
+    }
+}
+
+fn run_editor(text: &str, screen: &mut Screen) -> Result<String, io::Error> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+    // Editor may come with arguments, e.g. `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(DEFAULT_EDITOR);
+
+    // Created with a random name only the user can access, it is removed when dropped
+    let mut file = tempfile::Builder::new()
+        .prefix("rudo-notes-")
+        .suffix(".md")
+        .tempfile()?;
+    file.write_all(text.as_bytes())?;
+    file.flush()?;
+    let path = file.path();
+
+    screen.raw_mode.suspend_raw_mode()?;
+    let status = process::Command::new(program)
+        .args(words)
+        .arg(path)
+        .status();
+    screen.raw_mode.activate_raw_mode()?;
+    // Editor leaves the screen in any state, everything is drawn again
+    screen.terminal.clear()?;
+
+    match status {
+        // Editors may replace the file, so it is read by its path
+        Ok(status) if status.success() => fs::read_to_string(path),
+        Ok(status) => Err(io::Error::other(format!(
+            "{} exited with {}",
+            program, status
+        ))),
+        Err(error) => Err(io::Error::new(
+            error.kind(),
+            format!("cannot run {}: {}", program, error),
+        )),
+    }
+}
+
+/// Saves pending changes and returns `true` if the app can exit. When saving fails
+/// the app stays open to show the error, unless `force` is set.
+fn quit(app: &mut App, session: &Session, screen: &mut Screen, force: bool) -> bool {
+    if save_app_data(app, &session.task_list).is_err() && !force {
+        return false;
+    }
+
+    // Terminal might be already gone
+    let _ = screen.terminal.clear();
+    true
+}
+
+fn save_app_data(app: &mut App, task_list: &TaskList) -> Result<(), io::Error> {
+    if !app.has_unsaved_changes() {
+        return Ok(());
+    }
+
+    let result = dump(&task_list.path, &Data::new(app.list.items.clone()));
+
+    match &result {
+        Ok(()) => app.mark_as_saved(),
+        Err(error) => {
+            app.mark_as_changed();
+            app.status_message = Some(StatusMessage::SaveError(error.to_string()));
+        }
+    }
+
+    result
+}
+
+/// Loads items, offering to restore a backup if the data file is corrupt. Returns `None` if the
+/// app should not start. Items restored from a backup are marked with `true` as they are not
+/// saved yet.
+fn load_app_data(task_list: &TaskList) -> Option<(Vec<TodoItem>, bool)> {
+    let error = match get_app_data(&task_list.path) {
+        Ok(items) => return Some((items, false)),
+        Err(error) => error,
+    };
+    eprintln!("rudo: {}", error);
+
+    let path = match error {
+        // Starting with an empty list would overwrite the file on the next save
+        LoadError::Read(_, _) | LoadError::UnsupportedVersion(_, _) => return None,
+        LoadError::Corrupt(path, _) => path,
+    };
+
+    match quarantine(&path) {
+        Ok(quarantine_path) => println!("Corrupt file is moved to {}", quarantine_path.display()),
+        Err(error) => {
+            eprintln!("rudo: cannot move corrupt file aside: {}", error);
+            return None;
+        }
+    }
+
+    if let Some((backup_path, items)) = find_latest_backup(&path) {
+        let question = format!(
+            "Load {} tasks from backup {}? [Y/n]",
+            items.len(),
+            backup_path.display()
+        );
+        if confirm(&question, true).ok()? {
+            return Some((items, true));
+        }
+    }
+
+    if confirm("Start with an empty list? [y/N]", false).ok()? {
+        Some((vec![], false))
+    } else {
+        None
+    }
 }
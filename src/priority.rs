@@ -0,0 +1,85 @@
+use std::fmt::{Display, Formatter, Result};
+
+use serde::{Deserialize, Serialize};
+
+// Typed in the task input as `!`, `!!` or `!!!`
+const PRIORITY_MARKER: char = '!';
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    #[default]
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl Priority {
+    const LEVELS: [Priority; 4] = [
+        Priority::None,
+        Priority::Low,
+        Priority::Medium,
+        Priority::High,
+    ];
+
+    /// Parses a word made only of markers, e.g. `!!` is medium priority.
+    pub fn from_marker(word: &str) -> Option<Priority> {
+        if word.is_empty() || word.chars().any(|c| c != PRIORITY_MARKER) {
+            return None;
+        }
+
+        Priority::LEVELS.get(word.chars().count()).copied()
+    }
+
+    pub fn get_marker(self) -> String {
+        PRIORITY_MARKER.to_string().repeat(self as usize)
+    }
+
+    pub fn raise(self) -> Priority {
+        Priority::LEVELS[(self as usize + 1).min(Priority::LEVELS.len() - 1)]
+    }
+
+    pub fn lower(self) -> Priority {
+        Priority::LEVELS[(self as usize).saturating_sub(1)]
+    }
+}
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Priority::None => "none",
+                Priority::Low => "low",
+                Priority::Medium => "medium",
+                Priority::High => "high",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_markers() {
+        assert_eq!(Priority::from_marker("!"), Some(Priority::Low));
+        assert_eq!(Priority::from_marker("!!!"), Some(Priority::High));
+        assert_eq!(Priority::from_marker("!!!!"), None);
+        assert_eq!(Priority::from_marker("!?"), None);
+        assert_eq!(Priority::from_marker(""), None);
+        assert_eq!(Priority::High.get_marker(), "!!!");
+        assert_eq!(Priority::None.get_marker(), "");
+    }
+
+    #[test]
+    fn it_raises_and_lowers_within_bounds() {
+        assert_eq!(Priority::None.raise(), Priority::Low);
+        assert_eq!(Priority::High.raise(), Priority::High);
+        assert_eq!(Priority::Medium.lower(), Priority::Low);
+        assert_eq!(Priority::None.lower(), Priority::None);
+    }
+}
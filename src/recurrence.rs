@@ -0,0 +1,303 @@
+use chrono::{Datelike, Duration, NaiveDate, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::due_date::{DueDate, WEEKDAY_NAMES};
+use crate::todo_item::TodoItem;
+
+// Longest interval of `*3d` and `*+3d` rules, about ten years
+const MAX_INTERVAL_DAYS: u32 = 3650;
+
+/// How a task repeats. Completing a recurring task adds its next occurrence to the list.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Recurrence {
+    Daily,
+    // Repeats on the given weekdays, or on the weekday of the due date when there are none
+    Weekly(Vec<Weekday>),
+    // Repeats on the day of month of the due date, the last day in shorter months
+    Monthly,
+    EveryDays(u32),
+    // Counted from the day the task is completed rather than from its due date
+    DaysAfterCompletion(u32),
+}
+
+impl Recurrence {
+    /// Parses the word following `*` in the task input: `daily`, `weekly`, `weekly:mon,thu`,
+    /// `monthly`, `3d` for every 3 days or `+3d` for 3 days after completion.
+    pub fn parse(word: &str) -> Option<Recurrence> {
+        let word = word.to_lowercase();
+        match word.as_str() {
+            "daily" => return Some(Recurrence::Daily),
+            "weekly" => return Some(Recurrence::Weekly(vec![])),
+            "monthly" => return Some(Recurrence::Monthly),
+            _ => {}
+        }
+
+        if let Some(days) = word.strip_prefix("weekly:") {
+            let mut weekdays = days
+                .split(',')
+                .map(|day| day.parse::<Weekday>().ok())
+                .collect::<Option<Vec<_>>>()?;
+            weekdays.sort_by_key(|weekday| weekday.num_days_from_monday());
+            weekdays.dedup();
+            return Some(Recurrence::Weekly(weekdays));
+        }
+
+        let (is_after_completion, days) = match word.strip_prefix('+') {
+            Some(days) => (true, days),
+            None => (false, word.as_str()),
+        };
+        let days: u32 = days.strip_suffix('d')?.parse().ok()?;
+        match (days, is_after_completion) {
+            (0, _) => None,
+            (days, _) if days > MAX_INTERVAL_DAYS => None,
+            (days, true) => Some(Recurrence::DaysAfterCompletion(days)),
+            (days, false) => Some(Recurrence::EveryDays(days)),
+        }
+    }
+
+    /// Formats the rule the way it can be parsed back, e.g. `weekly:mon,thu`.
+    pub fn to_input_string(&self) -> String {
+        match self {
+            Recurrence::Daily => "daily".to_string(),
+            Recurrence::Weekly(weekdays) if weekdays.is_empty() => "weekly".to_string(),
+            Recurrence::Weekly(weekdays) => format!(
+                "weekly:{}",
+                weekdays
+                    .iter()
+                    .map(|weekday| WEEKDAY_NAMES[weekday.num_days_from_monday() as usize])
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            Recurrence::Monthly => "monthly".to_string(),
+            Recurrence::EveryDays(days) => format!("{}d", days),
+            Recurrence::DaysAfterCompletion(days) => format!("+{}d", days),
+        }
+    }
+
+    /// Due date of the occurrence following the one due on `due` and completed `today`.
+    /// Occurrences which would be past already are skipped, tasks without a due date repeat
+    /// from today. The time of the day is kept. Returns `None` when the date is out of range.
+    pub fn get_next_due(&self, due: Option<DueDate>, today: NaiveDate) -> Option<DueDate> {
+        let time = due.and_then(|due| due.time);
+        let start = match (self, due) {
+            (Recurrence::DaysAfterCompletion(_), _) | (_, None) => today,
+            (_, Some(due)) => due.date,
+        };
+
+        let mut date = start;
+        let mut count = 0;
+        loop {
+            count += 1;
+            date = match self {
+                Recurrence::Daily => start.checked_add_signed(Duration::days(count))?,
+                Recurrence::Weekly(weekdays) if weekdays.is_empty() => {
+                    start.checked_add_signed(Duration::weeks(count))?
+                }
+                Recurrence::Weekly(weekdays) => (1..=7)
+                    .filter_map(|days| date.checked_add_signed(Duration::days(days)))
+                    .find(|date| weekdays.contains(&date.weekday()))?,
+                Recurrence::Monthly => add_months(start, count as u32)?,
+                Recurrence::EveryDays(days) | Recurrence::DaysAfterCompletion(days) => {
+                    start.checked_add_signed(Duration::days(count * *days as i64))?
+                }
+            };
+            if date > today {
+                break;
+            }
+        }
+
+        Some(DueDate { date, time })
+    }
+}
+
+// Keeps the day of month where possible, e.g. a month after Jan 31 is Feb 29 in a leap year
+fn add_months(date: NaiveDate, months: u32) -> Option<NaiveDate> {
+    let month_index = date.month0().checked_add(months)?;
+    let year = date.year().checked_add((month_index / 12) as i32)?;
+    let month = month_index % 12 + 1;
+
+    (1..=date.day())
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+}
+
+/// Adds the next occurrence of a completed recurring task to the items and returns it. The
+/// occurrence takes over the recurrence, so reopening and completing the done task again does
+/// not add another one. Nothing is added when the next due date is out of range.
+pub fn spawn_next_occurrence(
+    items: &mut Vec<TodoItem>,
+    id: Uuid,
+    today: NaiveDate,
+) -> Option<TodoItem> {
+    let item = items
+        .iter_mut()
+        .find(|item| item.id == id && item.completed)?;
+    let next_due = item.recurrence.as_ref()?.get_next_due(item.due, today)?;
+    let recurrence = item.recurrence.take()?;
+
+    let mut next = item.clone();
+    next.id = Uuid::new_v4();
+    next.completed = false;
+    next.completed_date = None;
+    next.created_date = Utc::now();
+    next.updated_date = next.created_date;
+    next.due = Some(next_due);
+    next.recurrence = Some(recurrence);
+    items.push(next.clone());
+
+    Some(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveTime;
+
+    // Wednesday
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd(2024, 5, 1)
+    }
+
+    fn due_on(date: NaiveDate) -> Option<DueDate> {
+        Some(DueDate { date, time: None })
+    }
+
+    #[test]
+    fn it_parses_rules() {
+        assert_eq!(Recurrence::parse("Daily"), Some(Recurrence::Daily));
+        assert_eq!(
+            Recurrence::parse("weekly:thu,mon,thu"),
+            Some(Recurrence::Weekly(vec![Weekday::Mon, Weekday::Thu]))
+        );
+        assert_eq!(Recurrence::parse("3d"), Some(Recurrence::EveryDays(3)));
+        assert_eq!(
+            Recurrence::parse("+10d"),
+            Some(Recurrence::DaysAfterCompletion(10))
+        );
+        assert_eq!(Recurrence::parse("0d"), None);
+        assert_eq!(Recurrence::parse("100000000d"), None);
+        assert_eq!(
+            Recurrence::parse("3650d"),
+            Some(Recurrence::EveryDays(3650))
+        );
+        assert_eq!(Recurrence::parse("weekly:someday"), None);
+        assert_eq!(Recurrence::parse("often"), None);
+
+        let rule = Recurrence::parse("weekly:mon,thu").unwrap();
+        assert_eq!(rule.to_input_string(), "weekly:mon,thu");
+    }
+
+    #[test]
+    fn it_gets_next_due_date() {
+        let date = |month, day| NaiveDate::from_ymd(2024, month, day);
+
+        let due = DueDate {
+            date: today(),
+            time: Some(NaiveTime::from_hms(9, 0, 0)),
+        };
+        assert_eq!(
+            Recurrence::Daily.get_next_due(Some(due), today()),
+            Some(DueDate {
+                date: date(5, 2),
+                time: due.time
+            })
+        );
+        // Overdue occurrences are skipped
+        assert_eq!(
+            Recurrence::Weekly(vec![])
+                .get_next_due(due_on(date(4, 10)), today())
+                .unwrap()
+                .date,
+            date(5, 8)
+        );
+        assert_eq!(
+            Recurrence::Weekly(vec![Weekday::Mon, Weekday::Thu])
+                .get_next_due(due_on(today()), today())
+                .unwrap()
+                .date,
+            date(5, 2)
+        );
+        assert_eq!(
+            Recurrence::Monthly
+                .get_next_due(due_on(date(1, 31)), date(2, 5))
+                .unwrap()
+                .date,
+            date(2, 29)
+        );
+        assert_eq!(
+            Recurrence::Monthly
+                .get_next_due(due_on(date(1, 31)), date(3, 5))
+                .unwrap()
+                .date,
+            date(3, 31)
+        );
+        assert_eq!(
+            Recurrence::Daily
+                .get_next_due(due_on(date(5, 3)), today())
+                .unwrap()
+                .date,
+            date(5, 4)
+        );
+        assert_eq!(
+            Recurrence::EveryDays(3)
+                .get_next_due(None, today())
+                .unwrap()
+                .date,
+            date(5, 4)
+        );
+        assert_eq!(
+            Recurrence::DaysAfterCompletion(10)
+                .get_next_due(due_on(date(6, 1)), today())
+                .unwrap()
+                .date,
+            date(5, 11)
+        );
+    }
+
+    #[test]
+    fn it_does_not_overflow_dates() {
+        let last_day = due_on(chrono::naive::MAX_DATE);
+
+        assert_eq!(Recurrence::Daily.get_next_due(last_day, today()), None);
+        assert_eq!(Recurrence::Monthly.get_next_due(last_day, today()), None);
+        assert_eq!(
+            Recurrence::Weekly(vec![Weekday::Mon]).get_next_due(last_day, today()),
+            None
+        );
+        assert_eq!(
+            Recurrence::EveryDays(MAX_INTERVAL_DAYS).get_next_due(last_day, today()),
+            None
+        );
+
+        let mut item = TodoItem::new("chore");
+        item.recurrence = Some(Recurrence::Daily);
+        item.due = last_day;
+        item.set_completion(true);
+        let id = item.id;
+        let mut items = vec![item];
+        assert!(spawn_next_occurrence(&mut items, id, today()).is_none());
+        assert_eq!(items.len(), 1);
+    }
+
+    #[test]
+    fn it_spawns_next_occurrence() {
+        let mut item = TodoItem::new("rotate on-call notes");
+        item.recurrence = Some(Recurrence::Weekly(vec![]));
+        item.due = due_on(today());
+        let id = item.id;
+        let mut items = vec![item];
+
+        assert!(spawn_next_occurrence(&mut items, id, today()).is_none());
+        items[0].set_completion(true);
+        let next = spawn_next_occurrence(&mut items, id, today()).unwrap();
+
+        assert_eq!(items.len(), 2);
+        assert!(!next.completed);
+        assert_eq!(next.due, due_on(NaiveDate::from_ymd(2024, 5, 8)));
+        assert_eq!(next.recurrence, Some(Recurrence::Weekly(vec![])));
+        assert_eq!(items[0].recurrence, None);
+        assert!(spawn_next_occurrence(&mut items, id, today()).is_none());
+    }
+}
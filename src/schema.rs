@@ -0,0 +1,316 @@
+use std::collections::BTreeSet;
+
+use chrono::{TimeZone, Utc};
+use serde_json::Value;
+
+use crate::item_input::parse_tag;
+
+/// Version of the stored document written by this build. Bump it together with a new
+/// migration whenever the stored format changes.
+pub const CURRENT_SCHEMA_VERSION: u64 = 9;
+
+// Each migration upgrades a document from the version equal to its index to the next one
+const MIGRATIONS: [fn(Value) -> Result<Value, String>; CURRENT_SCHEMA_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+    migrate_v5_to_v6,
+    migrate_v6_to_v7,
+    migrate_v7_to_v8,
+    migrate_v8_to_v9,
+];
+
+#[derive(Debug, PartialEq)]
+pub enum MigrationError {
+    // Document is written by a newer rudo, reading it could drop the fields we do not know
+    UnsupportedVersion(u64),
+    Invalid(String),
+}
+
+/// Upgrades a stored document of any known version to the current one.
+pub fn migrate(mut document: Value) -> Result<Value, MigrationError> {
+    let version = get_schema_version(&document)?;
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(MigrationError::UnsupportedVersion(version));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        document = migration(document).map_err(MigrationError::Invalid)?;
+    }
+
+    Ok(document)
+}
+
+fn get_schema_version(document: &Value) -> Result<u64, MigrationError> {
+    match document.get("schema_version") {
+        // Documents written before versioning was introduced
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| MigrationError::Invalid(format!("invalid schema version {}", version))),
+    }
+}
+
+// Version 0 stores dates as UTC without timezone and sub-second precision,
+// version 1 uses RFC 3339
+fn migrate_v0_to_v1(mut document: Value) -> Result<Value, String> {
+    const V0_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+    for item in get_items_mut(&mut document)? {
+        for field in &["created_date", "updated_date"] {
+            let date = item
+                .get(*field)
+                .and_then(Value::as_str)
+                .ok_or(format!("missing {}", field))?;
+            let date = Utc
+                .datetime_from_str(date, V0_DATE_FORMAT)
+                .map_err(|error| format!("invalid {} '{}': {}", field, date, error))?;
+
+            item[*field] = Value::String(date.to_rfc3339());
+        }
+    }
+
+    document["schema_version"] = Value::from(1);
+    Ok(document)
+}
+
+// Version 2 adds due dates
+fn migrate_v1_to_v2(document: Value) -> Result<Value, String> {
+    add_item_field(document, "due", Value::Null, 2)
+}
+
+// Version 3 adds priorities
+fn migrate_v2_to_v3(document: Value) -> Result<Value, String> {
+    add_item_field(document, "priority", Value::from("none"), 3)
+}
+
+// Version 4 adds tags, `#tag` words are moved from names to them the way the task input does
+fn migrate_v3_to_v4(mut document: Value) -> Result<Value, String> {
+    for item in get_items_mut(&mut document)? {
+        let name = item
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or("missing name")?;
+        let (tag_words, name_words): (Vec<&str>, Vec<&str>) = name
+            .split_whitespace()
+            .partition(|word| parse_tag(word).is_some());
+        let mut tags: BTreeSet<String> = match item.get("tags") {
+            Some(tags) => {
+                serde_json::from_value(tags.clone()).map_err(|error| error.to_string())?
+            }
+            None => BTreeSet::new(),
+        };
+
+        // Task made only of tags keeps its name
+        if !tag_words.is_empty() && !name_words.is_empty() {
+            tags.extend(tag_words.into_iter().filter_map(parse_tag));
+            item["name"] = Value::from(name_words.join(" "));
+        }
+        item["tags"] = Value::from(tags.into_iter().collect::<Vec<_>>());
+    }
+
+    document["schema_version"] = Value::from(4);
+    Ok(document)
+}
+
+// Version 5 adds subtasks
+fn migrate_v4_to_v5(document: Value) -> Result<Value, String> {
+    add_item_field(document, "parent_id", Value::Null, 5)
+}
+
+// Version 6 adds notes
+fn migrate_v5_to_v6(document: Value) -> Result<Value, String> {
+    add_item_field(document, "notes", Value::from(""), 6)
+}
+
+// Version 7 stores when tasks are completed, tasks completed before count from their last update
+fn migrate_v6_to_v7(mut document: Value) -> Result<Value, String> {
+    for item in get_items_mut(&mut document)? {
+        if item.get("completed_date").is_some() {
+            continue;
+        }
+        let completed_date = match item.get("completed") {
+            Some(Value::Bool(true)) => item
+                .get("updated_date")
+                .cloned()
+                .ok_or("missing updated_date")?,
+            _ => Value::Null,
+        };
+        item["completed_date"] = completed_date;
+    }
+
+    document["schema_version"] = Value::from(7);
+    Ok(document)
+}
+
+// Version 8 adds recurring tasks
+fn migrate_v7_to_v8(document: Value) -> Result<Value, String> {
+    add_item_field(document, "recurrence", Value::Null, 8)
+}
+
+// Version 9 adds the custom order of tasks
+fn migrate_v8_to_v9(document: Value) -> Result<Value, String> {
+    add_item_field(document, "position", Value::Null, 9)
+}
+
+fn get_items_mut(document: &mut Value) -> Result<&mut Vec<Value>, String> {
+    document
+        .get_mut("items")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| "missing items".to_string())
+}
+
+// Gives items without the field its default value and sets the version of the document
+fn add_item_field(
+    mut document: Value,
+    field: &str,
+    default: Value,
+    version: u64,
+) -> Result<Value, String> {
+    for item in get_items_mut(&mut document)? {
+        let item = item.as_object_mut().ok_or("invalid item")?;
+        item.entry(field).or_insert_with(|| default.clone());
+    }
+
+    document["schema_version"] = Value::from(version);
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::due_date::DueDate;
+    use crate::priority::Priority;
+    use crate::recurrence::Recurrence;
+    use crate::storage::Data;
+    use chrono::{NaiveDate, NaiveTime, Weekday};
+
+    // Documents as they were written by every schema version
+    static FIXTURES: [&str; 10] = [
+        include_str!("../fixtures/schema/v0.json"),
+        include_str!("../fixtures/schema/v1.json"),
+        include_str!("../fixtures/schema/v2.json"),
+        include_str!("../fixtures/schema/v3.json"),
+        include_str!("../fixtures/schema/v4.json"),
+        include_str!("../fixtures/schema/v5.json"),
+        include_str!("../fixtures/schema/v6.json"),
+        include_str!("../fixtures/schema/v7.json"),
+        include_str!("../fixtures/schema/v8.json"),
+        include_str!("../fixtures/schema/v9.json"),
+    ];
+
+    #[test]
+    fn it_has_fixture_for_every_version() {
+        assert_eq!(FIXTURES.len() as u64, CURRENT_SCHEMA_VERSION + 1);
+    }
+
+    #[test]
+    fn it_migrates_and_round_trips_every_version() {
+        for (version, fixture) in FIXTURES.iter().enumerate() {
+            let document = migrate(serde_json::from_str(fixture).unwrap()).unwrap();
+            let data: Data = serde_json::from_value(document).unwrap();
+
+            assert_eq!(data.schema_version, CURRENT_SCHEMA_VERSION);
+            assert_eq!(data.items.len(), 2);
+            assert_eq!(data.items[0].name, "Write release notes");
+            assert!(data.items[1].completed);
+            assert_eq!(
+                data.items[0].created_date,
+                Utc.ymd(2020, 12, 24).and_hms(18, 30, 5)
+            );
+
+            // Fields are kept from the version they were added in
+            let due = DueDate {
+                date: NaiveDate::from_ymd(2021, 1, 10),
+                time: Some(NaiveTime::from_hms(9, 0, 0)),
+            };
+            assert_eq!(data.items[0].due, Some(due).filter(|_| version >= 2));
+            assert_eq!(data.items[1].due, None);
+            let priority = if version >= 3 {
+                Priority::High
+            } else {
+                Priority::None
+            };
+            assert_eq!(data.items[0].priority, priority);
+            // Tags typed into names before version 4 are moved out of them
+            assert_eq!(data.items[0].tags, ["work".to_string()].into());
+            assert!(data.items[1].tags.is_empty());
+            assert_eq!(data.items[0].parent_id, None);
+            let parent_id = Some(data.items[0].id).filter(|_| version >= 5);
+            assert_eq!(data.items[1].parent_id, parent_id);
+            let notes = if version >= 6 {
+                "Mention the new sync"
+            } else {
+                ""
+            };
+            assert_eq!(data.items[0].notes, notes);
+            assert_eq!(data.items[0].completed_date, None);
+            assert_eq!(
+                data.items[1].completed_date,
+                Some(data.items[1].updated_date)
+            );
+            let recurrence = Recurrence::Weekly(vec![Weekday::Mon, Weekday::Thu]);
+            assert_eq!(
+                data.items[0].recurrence,
+                Some(recurrence).filter(|_| version >= 8)
+            );
+            let positions = (data.items[0].position, data.items[1].position);
+            let expected = if version >= 9 {
+                (Some(1), Some(0))
+            } else {
+                (None, None)
+            };
+            assert_eq!(positions, expected);
+
+            // Saved document is loaded back without changes
+            let saved = serde_json::to_value(&data).unwrap();
+            let reloaded: Data = serde_json::from_value(migrate(saved).unwrap()).unwrap();
+            assert_eq!(
+                serde_json::to_value(&reloaded).unwrap(),
+                serde_json::to_value(&data).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn it_moves_tags_out_of_names() {
+        let document = serde_json::json!({ "schema_version": 3, "items": [
+            { "name": "fix  CI #Work #ci-1" },
+            { "name": "#later", "tags": ["home"] },
+            { "name": "issue #", "tags": ["home"] },
+            { "name": "Fix crash #42 #ui" },
+        ] });
+        let document = migrate_v3_to_v4(document).unwrap();
+
+        assert_eq!(document["items"][0]["name"], "fix CI");
+        assert_eq!(
+            document["items"][0]["tags"],
+            serde_json::json!(["ci-1", "work"])
+        );
+        assert_eq!(document["items"][1]["name"], "#later");
+        assert_eq!(document["items"][1]["tags"], serde_json::json!(["home"]));
+        assert_eq!(document["items"][2]["name"], "issue #");
+        // Issue references are not tags
+        assert_eq!(document["items"][3]["name"], "Fix crash #42");
+        assert_eq!(document["items"][3]["tags"], serde_json::json!(["ui"]));
+    }
+
+    #[test]
+    fn it_rejects_documents_from_newer_versions() {
+        let document = serde_json::json!({ "schema_version": 99, "items": [] });
+
+        assert_eq!(
+            migrate(document),
+            Err(MigrationError::UnsupportedVersion(99))
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_v0_dates() {
+        let document = serde_json::json!({ "items": [{ "created_date": "yesterday" }] });
+
+        assert!(matches!(migrate(document), Err(MigrationError::Invalid(_))));
+    }
+}
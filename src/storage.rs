@@ -0,0 +1,263 @@
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::{migrate, MigrationError, CURRENT_SCHEMA_VERSION};
+use crate::todo_item::TodoItem;
+
+// Number of previous versions kept next to the data file as `todos.json.1..N`
+const BACKUP_COUNT: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Data {
+    pub schema_version: u64,
+    pub items: Vec<TodoItem>,
+}
+
+impl Data {
+    pub fn new(items: Vec<TodoItem>) -> Self {
+        Data {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            items,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    // File exists but cannot be read, e.g. permission denied
+    Read(PathBuf, io::Error),
+    // File is read, but its content is not valid
+    Corrupt(PathBuf, String),
+    // File is written by a newer rudo
+    UnsupportedVersion(PathBuf, u64),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Read(path, error) => write!(f, "cannot read {}: {}", path.display(), error),
+            LoadError::Corrupt(path, error) => {
+                write!(f, "{} is corrupt: {}", path.display(), error)
+            }
+            LoadError::UnsupportedVersion(path, version) => write!(
+                f,
+                "{} uses schema version {}, update rudo to open it",
+                path.display(),
+                version
+            ),
+        }
+    }
+}
+
+pub fn dump(path_to_file: &Path, data: &Data) -> io::Result<()> {
+    if let Some(path_to_file_dir) = path_to_file.parent() {
+        fs::create_dir_all(path_to_file_dir)?;
+    }
+
+    save(data, path_to_file)
+}
+
+/// Reads stored items. Missing file means there are no items yet.
+pub fn get_app_data(path: &Path) -> Result<Vec<TodoItem>, LoadError> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(LoadError::Read(path.to_path_buf(), error)),
+    };
+
+    let corrupt = |error: String| LoadError::Corrupt(path.to_path_buf(), error);
+
+    let document = serde_json::from_str(&content).map_err(|error| corrupt(error.to_string()))?;
+    let document = match migrate(document) {
+        Ok(document) => document,
+        Err(MigrationError::UnsupportedVersion(version)) => {
+            return Err(LoadError::UnsupportedVersion(path.to_path_buf(), version))
+        }
+        Err(MigrationError::Invalid(error)) => return Err(corrupt(error)),
+    };
+
+    match serde_json::from_value::<Data>(document) {
+        Ok(data) => Ok(data.items),
+        Err(error) => Err(corrupt(error.to_string())),
+    }
+}
+
+/// Moves a corrupt file aside, so it is not overwritten by the next save.
+/// Returns the new location of the file.
+pub fn quarantine(path: &Path) -> io::Result<PathBuf> {
+    let quarantine_path = get_sibling_path(
+        path,
+        &format!("corrupt-{}", Utc::now().format("%Y%m%d%H%M%S")),
+    );
+    fs::rename(path, &quarantine_path)?;
+    sync_parent_dir(path)?;
+
+    Ok(quarantine_path)
+}
+
+/// Returns the most recent backup which can be loaded together with its items.
+pub fn find_latest_backup(path: &Path) -> Option<(PathBuf, Vec<TodoItem>)> {
+    (1..=BACKUP_COUNT)
+        .map(|index| get_backup_path(path, index))
+        .filter(|backup| backup.exists())
+        .find_map(|backup| get_app_data(&backup).ok().map(|items| (backup, items)))
+}
+
+/// Replaces the file atomically, so it contains either the old or the new data
+/// even if the app is killed mid-write.
+fn save(data: &Data, path: &Path) -> io::Result<()> {
+    let content = serde_json::to_string(data)?;
+
+    let tmp_path = get_sibling_path(path, "tmp");
+    let mut tmp_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmp_path)?;
+    tmp_file.write_all(content.as_bytes())?;
+    tmp_file.sync_all()?;
+
+    if path.exists() {
+        rotate_backups(path)?;
+    }
+
+    fs::rename(&tmp_path, path)?;
+    sync_parent_dir(path)
+}
+
+// todos.json.1 is the latest backup, todos.json.N the oldest one
+fn rotate_backups(path: &Path) -> io::Result<()> {
+    for index in (1..BACKUP_COUNT).rev() {
+        let backup = get_backup_path(path, index);
+        if backup.exists() {
+            fs::rename(&backup, get_backup_path(path, index + 1))?;
+        }
+    }
+
+    fs::copy(path, get_backup_path(path, 1))?;
+    Ok(())
+}
+
+fn get_backup_path(path: &Path, index: usize) -> PathBuf {
+    get_sibling_path(path, &index.to_string())
+}
+
+fn get_sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(extension);
+
+    path.with_file_name(file_name)
+}
+
+// Makes the rename itself durable
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if dir != Path::new("") => File::open(dir)?.sync_all(),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn read_items(path: &Path) -> Vec<TodoItem> {
+        get_app_data(path).unwrap()
+    }
+
+    #[test]
+    fn it_saves_data_without_leaving_temporary_files() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("todos.json");
+
+        save(&Data::new(vec![TodoItem::new("A")]), &path).unwrap();
+
+        assert_eq!(read_items(&path)[0].name, "A");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn it_keeps_rolling_backups() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("todos.json");
+
+        for index in 0..BACKUP_COUNT + 2 {
+            save(&Data::new(vec![TodoItem::new(&index.to_string())]), &path).unwrap();
+        }
+
+        let last_index = BACKUP_COUNT + 1;
+        assert_eq!(read_items(&path)[0].name, last_index.to_string());
+        assert_eq!(
+            read_items(&get_backup_path(&path, 1))[0].name,
+            (last_index - 1).to_string()
+        );
+        assert_eq!(
+            read_items(&get_backup_path(&path, BACKUP_COUNT))[0].name,
+            (last_index - BACKUP_COUNT).to_string()
+        );
+        assert!(!get_backup_path(&path, BACKUP_COUNT + 1).exists());
+    }
+
+    #[test]
+    fn it_loads_missing_file_as_empty_list() {
+        let dir = tempdir().unwrap();
+
+        assert_eq!(
+            get_app_data(&dir.path().join("todos.json")).unwrap().len(),
+            0
+        );
+    }
+
+    #[test]
+    fn it_quarantines_corrupt_file_and_finds_latest_valid_backup() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("todos.json");
+        for name in &["A", "B"] {
+            save(&Data::new(vec![TodoItem::new(name)]), &path).unwrap();
+        }
+        fs::write(&path, "{\"items\": [").unwrap();
+        fs::write(get_backup_path(&path, 2), "[]").unwrap();
+        fs::rename(get_backup_path(&path, 1), get_backup_path(&path, 3)).unwrap();
+
+        assert!(matches!(get_app_data(&path), Err(LoadError::Corrupt(_, _))));
+
+        let quarantine_path = quarantine(&path).unwrap();
+        assert!(!path.exists());
+        assert_eq!(
+            fs::read_to_string(quarantine_path).unwrap(),
+            "{\"items\": ["
+        );
+
+        // Backup 2 is corrupt as well, so backup 3 is used
+        let (backup_path, items) = find_latest_backup(&path).unwrap();
+        assert_eq!(backup_path, get_backup_path(&path, 3));
+        assert_eq!(items[0].name, "A");
+    }
+
+    #[test]
+    fn it_fails_without_touching_existing_data() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("todos.json");
+        save(&Data::new(vec![TodoItem::new("A")]), &path).unwrap();
+
+        // Temporary file cannot be created when its path is taken by a directory
+        fs::create_dir(get_sibling_path(&path, "tmp")).unwrap();
+        let result = save(&Data::new(vec![]), &path);
+
+        assert!(result.is_err());
+        assert_eq!(read_items(&path)[0].name, "A");
+    }
+}
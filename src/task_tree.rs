@@ -0,0 +1,275 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::todo_item::TodoItem;
+
+/// Task as it is shown in the list tree.
+#[derive(Debug, Clone)]
+pub struct ListRow {
+    // Position of the task in the unfiltered list
+    pub index: usize,
+    pub item: TodoItem,
+    pub depth: usize,
+    // Number of done and all direct subtasks, `None` if the task has no subtasks
+    pub progress: Option<(usize, usize)>,
+    pub is_collapsed: bool,
+    // Name characters matched by the filter
+    pub matched_positions: BTreeSet<usize>,
+}
+
+/// Ids of the subtasks of a task, their subtasks and so on.
+pub fn get_descendant_ids(items: &[TodoItem], id: Uuid) -> HashSet<Uuid> {
+    let mut descendant_ids = HashSet::new();
+    let mut parent_ids = vec![id];
+    while let Some(parent_id) = parent_ids.pop() {
+        for item in items {
+            // Checking inserted ids keeps broken data with parent cycles from looping forever
+            if item.parent_id == Some(parent_id) && item.id != id && descendant_ids.insert(item.id)
+            {
+                parent_ids.push(item.id);
+            }
+        }
+    }
+
+    descendant_ids
+}
+
+/// Marks a task as done or not done. With `cascade` completing a task completes all its
+/// subtasks as well, while reopening it leaves them as they are.
+pub fn set_completion(items: &mut [TodoItem], id: Uuid, is_complete: bool, cascade: bool) {
+    let mut ids = if is_complete && cascade {
+        get_descendant_ids(items, id)
+    } else {
+        HashSet::new()
+    };
+    ids.insert(id);
+
+    for item in items.iter_mut() {
+        // Tasks which are done already keep their updated date
+        if ids.contains(&item.id) && item.completed != is_complete {
+            item.set_completion(is_complete);
+        }
+    }
+}
+
+/// Removes a task together with its subtasks.
+pub fn remove_with_subtasks(items: &mut Vec<TodoItem>, id: Uuid) {
+    let mut ids = get_descendant_ids(items, id);
+    ids.insert(id);
+    items.retain(|item| !ids.contains(&item.id));
+}
+
+/// Removes tasks together with their subtasks and returns them. Taken tasks whose parent
+/// stays become top-level.
+pub fn take_with_subtasks(items: &mut Vec<TodoItem>, ids: &HashSet<Uuid>) -> Vec<TodoItem> {
+    let mut taken_ids = ids.clone();
+    for &id in ids {
+        taken_ids.extend(get_descendant_ids(items, id));
+    }
+
+    let (mut taken, kept): (Vec<_>, Vec<_>) = items
+        .drain(..)
+        .partition(|item| taken_ids.contains(&item.id));
+    for item in &mut taken {
+        if item
+            .parent_id
+            .is_some_and(|parent_id| !taken_ids.contains(&parent_id))
+        {
+            item.parent_id = None;
+        }
+    }
+    *items = kept;
+
+    taken
+}
+
+/// Arranges matching tasks into a tree. `matches` are indexes of matching items with their
+/// matched name characters, in the order they should be shown. Ancestors of matching tasks are
+/// kept to show subtasks in context and are placed where their first matching subtask would be.
+/// Subtasks of collapsed tasks are hidden, tasks with a missing parent are shown at the top.
+pub fn build_rows(
+    items: &[TodoItem],
+    matches: Vec<(usize, BTreeSet<usize>)>,
+    collapsed: &HashSet<Uuid>,
+) -> Vec<ListRow> {
+    let indexes: HashMap<Uuid, usize> = items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| (item.id, index))
+        .collect();
+    let get_parent = |index: usize| {
+        items[index]
+            .parent_id
+            .and_then(|parent_id| indexes.get(&parent_id).copied())
+    };
+
+    let mut progress: HashMap<usize, (usize, usize)> = HashMap::new();
+    for (index, item) in items.iter().enumerate() {
+        if let Some(parent) = get_parent(index) {
+            let (done, total) = progress.entry(parent).or_default();
+            *done += item.completed as usize;
+            *total += 1;
+        }
+    }
+
+    // Rank decides the order among siblings, lower comes first
+    let mut ranks: HashMap<usize, usize> = HashMap::new();
+    let mut matched_positions: HashMap<usize, BTreeSet<usize>> = HashMap::new();
+    for (rank, (index, positions)) in matches.into_iter().enumerate() {
+        matched_positions.insert(index, positions);
+        let mut current = Some(index);
+        while let Some(index) = current {
+            // Ancestors ranked already have a lower rank
+            if ranks.contains_key(&index) {
+                break;
+            }
+            ranks.insert(index, rank);
+            current = get_parent(index);
+        }
+    }
+
+    let mut by_rank: Vec<usize> = ranks.keys().copied().collect();
+    by_rank.sort_by_key(|index| ranks[index]);
+    let mut children: HashMap<Option<usize>, Vec<usize>> = HashMap::new();
+    for &index in &by_rank {
+        children.entry(get_parent(index)).or_default().push(index);
+    }
+
+    let mut rows = vec![];
+    let mut visited = HashSet::new();
+    let roots = children.get(&None).cloned().unwrap_or_default();
+    // Tasks in a parent cycle have no root, they are shown at the top as well
+    for root in roots.into_iter().chain(by_rank) {
+        let mut stack = vec![(root, 0, false)];
+        while let Some((index, depth, is_hidden)) = stack.pop() {
+            if !visited.insert(index) {
+                continue;
+            }
+
+            let item = &items[index];
+            let is_collapsed = collapsed.contains(&item.id) && progress.contains_key(&index);
+            if !is_hidden {
+                rows.push(ListRow {
+                    index,
+                    item: item.clone(),
+                    depth,
+                    progress: progress.get(&index).copied(),
+                    is_collapsed,
+                    matched_positions: matched_positions.remove(&index).unwrap_or_default(),
+                });
+            }
+
+            if let Some(subtasks) = children.get(&Some(index)) {
+                stack.extend(
+                    subtasks
+                        .iter()
+                        .rev()
+                        .map(|&subtask| (subtask, depth + 1, is_hidden || is_collapsed)),
+                );
+            }
+        }
+    }
+
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Parent with two subtasks, the first of them has a subtask of its own
+    fn create_tree() -> Vec<TodoItem> {
+        let parent = TodoItem::new("parent");
+        let mut first = TodoItem::new("first");
+        first.parent_id = Some(parent.id);
+        let mut second = TodoItem::new("second");
+        second.parent_id = Some(parent.id);
+        second.set_completion(true);
+        let mut nested = TodoItem::new("nested");
+        nested.parent_id = Some(first.id);
+
+        vec![nested, parent, first, second, TodoItem::new("other")]
+    }
+
+    fn all_matches(items: &[TodoItem]) -> Vec<(usize, BTreeSet<usize>)> {
+        (0..items.len())
+            .map(|index| (index, BTreeSet::new()))
+            .collect()
+    }
+
+    fn get_names(rows: &[ListRow]) -> Vec<(&str, usize)> {
+        rows.iter()
+            .map(|row| (row.item.name.as_str(), row.depth))
+            .collect()
+    }
+
+    #[test]
+    fn it_builds_tree_rows() {
+        let items = create_tree();
+        let rows = build_rows(&items, all_matches(&items), &HashSet::new());
+
+        assert_eq!(
+            get_names(&rows),
+            vec![
+                ("parent", 0),
+                ("first", 1),
+                ("nested", 2),
+                ("second", 1),
+                ("other", 0)
+            ]
+        );
+        assert_eq!(rows[0].progress, Some((1, 2)));
+        assert_eq!(rows[2].progress, None);
+
+        let collapsed = vec![items[2].id].into_iter().collect();
+        let rows = build_rows(&items, all_matches(&items), &collapsed);
+        assert_eq!(rows.len(), 4);
+        assert!(rows[1].is_collapsed);
+    }
+
+    #[test]
+    fn it_keeps_ancestors_of_matching_tasks() {
+        let items = create_tree();
+        let matches = vec![(4, BTreeSet::new()), (0, vec![1].into_iter().collect())];
+        let rows = build_rows(&items, matches, &HashSet::new());
+
+        assert_eq!(
+            get_names(&rows),
+            vec![("other", 0), ("parent", 0), ("first", 1), ("nested", 2)]
+        );
+        assert_eq!(rows[3].matched_positions, vec![1].into_iter().collect());
+        assert!(rows[1].matched_positions.is_empty());
+    }
+
+    #[test]
+    fn it_completes_and_removes_subtasks() {
+        let mut items = create_tree();
+        let parent_id = items[1].id;
+
+        set_completion(&mut items, parent_id, true, false);
+        assert!(!items[0].completed);
+        set_completion(&mut items, parent_id, true, true);
+        assert!(items[..4].iter().all(|item| item.completed));
+        set_completion(&mut items, parent_id, false, true);
+        assert!(!items[1].completed);
+        assert!(items[0].completed);
+
+        remove_with_subtasks(&mut items, parent_id);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].name, "other");
+    }
+
+    #[test]
+    fn it_takes_tasks_with_subtasks() {
+        let mut items = create_tree();
+        let ids = vec![items[2].id, items[4].id].into_iter().collect();
+
+        let taken = take_with_subtasks(&mut items, &ids);
+        let names: Vec<&str> = taken.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, vec!["nested", "first", "other"]);
+        assert_eq!(taken[1].parent_id, None);
+        assert_eq!(taken[0].parent_id, Some(taken[1].id));
+        assert_eq!(items.len(), 2);
+    }
+}
@@ -0,0 +1,261 @@
+use termion::event::Key;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+// Older entries are dropped once the history is that long
+const HISTORY_LIMIT: usize = 100;
+
+/// Single line text input with a cursor, readline-like editing keys and history of entered texts.
+/// The cursor moves by graphemes, so a character with combining marks or an emoji is one step.
+#[derive(Debug, Default)]
+pub struct TextInput {
+    text: String,
+    // Byte index in the text, always on a grapheme boundary
+    cursor: usize,
+    // Text removed by the latest kill, inserted back with Ctrl-Y
+    killed: String,
+    history: Vec<String>,
+    // Entry of the history shown in the input while browsing it
+    history_index: Option<usize>,
+    // Text typed before browsing the history, restored after its newest entry
+    draft: String,
+}
+
+impl TextInput {
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// Replaces the text and puts the cursor at its end.
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.text.len();
+        self.history_index = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.set_text("");
+    }
+
+    /// Width of the text before the cursor in terminal columns.
+    pub fn get_cursor_width(&self) -> usize {
+        self.text[..self.cursor].width()
+    }
+
+    /// Remembers the text, so it can be recalled with Up. Repeated texts are kept once.
+    pub fn add_to_history(&mut self) {
+        self.history_index = None;
+        if self.text.trim().is_empty() || self.history.last() == Some(&self.text) {
+            return;
+        }
+        if self.history.len() == HISTORY_LIMIT {
+            self.history.remove(0);
+        }
+        self.history.push(self.text.clone());
+    }
+
+    /// Edits the text according to the key. Returns `false` for keys the input does not use.
+    pub fn handle_key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char(c) if !c.is_control() => self.insert(&c.to_string()),
+            Key::Left | Key::Ctrl('b') => self.cursor = self.get_previous_boundary(),
+            Key::Right | Key::Ctrl('f') => self.cursor = self.get_next_boundary(),
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = self.text.len(),
+            Key::Alt('b') => self.cursor = self.get_previous_word_start(),
+            Key::Alt('f') => self.cursor = self.get_next_word_end(),
+            Key::Backspace => {
+                let start = self.get_previous_boundary();
+                self.remove(start, self.cursor);
+            }
+            Key::Delete | Key::Ctrl('d') => {
+                let end = self.get_next_boundary();
+                self.remove(self.cursor, end);
+            }
+            Key::Ctrl('w') => self.kill(self.get_previous_word_start(), self.cursor),
+            Key::Alt('d') => self.kill(self.cursor, self.get_next_word_end()),
+            Key::Ctrl('u') => self.kill(0, self.cursor),
+            Key::Ctrl('k') => self.kill(self.cursor, self.text.len()),
+            Key::Ctrl('y') => self.insert(&self.killed.clone()),
+            Key::Up => self.recall_older(),
+            Key::Down => self.recall_newer(),
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn insert(&mut self, text: &str) {
+        self.text.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    fn remove(&mut self, start: usize, end: usize) -> String {
+        self.cursor = start;
+        self.text.drain(start..end).collect()
+    }
+
+    fn kill(&mut self, start: usize, end: usize) {
+        if start < end {
+            self.killed = self.remove(start, end);
+        }
+    }
+
+    fn get_previous_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(index, _)| index)
+    }
+
+    fn get_next_boundary(&self) -> usize {
+        self.text[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |grapheme| self.cursor + grapheme.len())
+    }
+
+    // Words are separated by whitespace, the way shells treat them
+    fn get_previous_word_start(&self) -> usize {
+        let mut start = self.cursor;
+        let mut is_in_word = false;
+        for (index, grapheme) in self.text[..self.cursor].grapheme_indices(true).rev() {
+            let is_space = grapheme.trim().is_empty();
+            if is_space && is_in_word {
+                break;
+            }
+            is_in_word |= !is_space;
+            start = index;
+        }
+
+        start
+    }
+
+    fn get_next_word_end(&self) -> usize {
+        let mut end = self.cursor;
+        let mut is_in_word = false;
+        for grapheme in self.text[self.cursor..].graphemes(true) {
+            let is_space = grapheme.trim().is_empty();
+            if is_space && is_in_word {
+                break;
+            }
+            is_in_word |= !is_space;
+            end += grapheme.len();
+        }
+
+        end
+    }
+
+    fn recall_older(&mut self) {
+        let index = match self.history_index {
+            Some(index) => index.saturating_sub(1),
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.text.clone();
+                self.history.len() - 1
+            }
+        };
+        self.show_history_entry(Some(index));
+    }
+
+    fn recall_newer(&mut self) {
+        match self.history_index {
+            Some(index) if index + 1 < self.history.len() => {
+                self.show_history_entry(Some(index + 1))
+            }
+            Some(_) => self.show_history_entry(None),
+            None => {}
+        }
+    }
+
+    fn show_history_entry(&mut self, index: Option<usize>) {
+        self.text = match index {
+            Some(index) => self.history[index].clone(),
+            None => std::mem::take(&mut self.draft),
+        };
+        self.cursor = self.text.len();
+        self.history_index = index;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_keys(input: &mut TextInput, keys: &[Key]) {
+        for key in keys {
+            input.handle_key(*key);
+        }
+    }
+
+    #[test]
+    fn it_edits_at_cursor() {
+        let mut input = TextInput::default();
+        input.set_text("pay bill");
+
+        type_keys(&mut input, &[Key::Home, Key::Delete, Key::Char('P')]);
+        type_keys(
+            &mut input,
+            &[Key::End, Key::Left, Key::Left, Key::Backspace],
+        );
+        assert_eq!(input.get_text(), "Pay bll");
+
+        type_keys(&mut input, &[Key::Right, Key::Right, Key::Char('s')]);
+        assert_eq!(input.get_text(), "Pay blls");
+        assert_eq!(input.get_cursor_width(), 8);
+        assert!(!input.handle_key(Key::Char('\n')));
+    }
+
+    #[test]
+    fn it_moves_by_graphemes() {
+        let mut input = TextInput::default();
+        input.set_text("cafe\u{301} 👍🏽");
+
+        type_keys(&mut input, &[Key::Backspace, Key::Left, Key::Left]);
+        assert_eq!(input.get_cursor_width(), 3);
+        input.handle_key(Key::Delete);
+        assert_eq!(input.get_text(), "caf ");
+    }
+
+    #[test]
+    fn it_kills_and_yanks_words() {
+        let mut input = TextInput::default();
+        input.set_text("write  release notes #work");
+
+        input.handle_key(Key::Ctrl('w'));
+        assert_eq!(input.get_text(), "write  release notes ");
+        type_keys(&mut input, &[Key::Alt('b'), Key::Alt('b'), Key::Ctrl('y')]);
+        assert_eq!(input.get_text(), "write  #workrelease notes ");
+
+        type_keys(&mut input, &[Key::Alt('f'), Key::Ctrl('u')]);
+        assert_eq!(input.get_text(), " notes ");
+        type_keys(&mut input, &[Key::End, Key::Ctrl('y')]);
+        assert_eq!(input.get_text(), " notes write  #workrelease");
+
+        type_keys(&mut input, &[Key::Home, Key::Alt('d')]);
+        assert_eq!(input.get_text(), " write  #workrelease");
+        type_keys(&mut input, &[Key::Alt('f'), Key::Ctrl('k')]);
+        assert_eq!(input.get_text(), " write");
+    }
+
+    #[test]
+    fn it_recalls_history() {
+        let mut input = TextInput::default();
+        for text in &["first", "second", "second", " "] {
+            input.set_text(text);
+            input.add_to_history();
+        }
+        input.set_text("draft");
+
+        input.handle_key(Key::Up);
+        assert_eq!(input.get_text(), "second");
+        type_keys(&mut input, &[Key::Up, Key::Up]);
+        assert_eq!(input.get_text(), "first");
+        input.handle_key(Key::Down);
+        assert_eq!(input.get_text(), "second");
+        input.handle_key(Key::Down);
+        assert_eq!(input.get_text(), "draft");
+        input.handle_key(Key::Down);
+        assert_eq!(input.get_text(), "draft");
+    }
+}
@@ -1,16 +1,42 @@
-use chrono::{DateTime, Utc};
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::due_date::DueDate;
+use crate::item_input::{DUE_DATE_PREFIX, RECURRENCE_PREFIX, TAG_PREFIX};
+use crate::priority::Priority;
+use crate::recurrence::Recurrence;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TodoItem {
     pub id: Uuid,
     pub name: String,
     pub completed: bool,
-    #[serde(with = "my_date_format")]
     pub created_date: DateTime<Utc>,
-    #[serde(with = "my_date_format")]
     pub updated_date: DateTime<Utc>,
+    #[serde(default)]
+    pub due: Option<DueDate>,
+    #[serde(default)]
+    pub priority: Priority,
+    // Lowercase tags typed as `#tag`, they are not a part of the name
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    // Task this one is a subtask of
+    #[serde(default)]
+    pub parent_id: Option<Uuid>,
+    // Free-form description, may span several lines
+    #[serde(default)]
+    pub notes: String,
+    // Tasks completed before it was stored take `updated_date` when the list is migrated
+    #[serde(default)]
+    pub completed_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    // Place in the custom order of the list, missing until tasks are moved by hand
+    #[serde(default)]
+    pub position: Option<u32>,
 }
 
 impl TodoItem {
@@ -21,12 +47,25 @@ impl TodoItem {
             completed: false,
             created_date: Utc::now(),
             updated_date: Utc::now(),
+            due: None,
+            priority: Priority::None,
+            tags: BTreeSet::new(),
+            parent_id: None,
+            notes: String::new(),
+            completed_date: None,
+            recurrence: None,
+            position: None,
         }
     }
 
     pub fn set_completion(&mut self, is_complete: bool) -> &Self {
         self.completed = is_complete;
         self.updated_date = Utc::now();
+        self.completed_date = if is_complete {
+            Some(self.updated_date)
+        } else {
+            None
+        };
 
         self
     }
@@ -37,6 +76,111 @@ impl TodoItem {
 
         self
     }
+
+    pub fn set_due(&mut self, due: Option<DueDate>) -> &Self {
+        self.due = due;
+        self.updated_date = Utc::now();
+
+        self
+    }
+
+    pub fn set_priority(&mut self, priority: Priority) -> &Self {
+        self.priority = priority;
+        self.updated_date = Utc::now();
+
+        self
+    }
+
+    pub fn set_tags(&mut self, tags: BTreeSet<String>) -> &Self {
+        self.tags = tags;
+        self.updated_date = Utc::now();
+
+        self
+    }
+
+    pub fn set_recurrence(&mut self, recurrence: Option<Recurrence>) -> &Self {
+        self.recurrence = recurrence;
+        self.updated_date = Utc::now();
+
+        self
+    }
+
+    pub fn set_notes(&mut self, notes: &str) -> &Self {
+        self.notes = notes.to_string();
+        self.updated_date = Utc::now();
+
+        self
+    }
+
+    pub fn get_completed_date(&self) -> Option<DateTime<Utc>> {
+        if self.completed {
+            self.completed_date.or(Some(self.updated_date))
+        } else {
+            None
+        }
+    }
+
+    pub fn to_row_string(&self, today: NaiveDate) -> String {
+        let mut row = format!("{}{}", self.get_row_prefix(), self.name);
+        for tag in self.get_tag_strings() {
+            row = format!("{} {}", row, tag);
+        }
+
+        if let Some(due) = self.get_due_string(today) {
+            row = format!("{}  {}", row, due);
+        }
+
+        match self.get_recurrence_string() {
+            Some(recurrence) => format!("{} {}", row, recurrence),
+            None => row,
+        }
+    }
+
+    /// Completion and priority shown before the name, e.g. `[ ] - !! `.
+    pub fn get_row_prefix(&self) -> String {
+        let checkbox = if self.completed { 'X' } else { ' ' };
+        match self.priority {
+            Priority::None => format!("[{}] - ", checkbox),
+            priority => format!("[{}] - {} ", checkbox, priority.get_marker()),
+        }
+    }
+
+    pub fn get_tag_strings(&self) -> Vec<String> {
+        self.tags
+            .iter()
+            .map(|tag| format!("{}{}", TAG_PREFIX, tag))
+            .collect()
+    }
+
+    pub fn get_due_string(&self, today: NaiveDate) -> Option<String> {
+        self.due
+            .map(|due| format!("{}{}", DUE_DATE_PREFIX, due.to_display_string(today)))
+    }
+
+    pub fn get_recurrence_string(&self) -> Option<String> {
+        self.recurrence
+            .as_ref()
+            .map(|recurrence| format!("{}{}", RECURRENCE_PREFIX, recurrence.to_input_string()))
+    }
+
+    /// Formats the task the way it is typed in the prompt, so it can be edited.
+    pub fn to_input_string(&self) -> String {
+        let mut input = self.name.clone();
+        for tag in self.get_tag_strings() {
+            input = format!("{} {}", input, tag);
+        }
+        if self.priority != Priority::None {
+            input = format!("{} {}", input, self.priority.get_marker());
+        }
+        if let Some(due) = self.due {
+            input = format!("{} {}{}", input, DUE_DATE_PREFIX, due.to_input_string());
+        }
+        if let Some(recurrence) = self.get_recurrence_string() {
+            input = format!("{} {}", input, recurrence);
+        }
+
+        input
+    }
 }
 
 #[cfg(test)]
@@ -49,7 +193,7 @@ mod tests {
         let item = TodoItem::new(name);
 
         assert_eq!(item.name, name);
-        assert_eq!(item.completed, false);
+        assert!(!item.completed);
     }
 
     #[test]
@@ -57,9 +201,13 @@ mod tests {
         let name = "test task";
         let mut item = TodoItem::new(name);
 
-        assert_eq!(item.completed, false);
+        assert!(!item.completed);
         item.set_completion(true);
-        assert_eq!(item.completed, true);
+        assert!(item.completed);
+        assert_eq!(item.get_completed_date(), Some(item.updated_date));
+
+        item.set_completion(false);
+        assert_eq!(item.get_completed_date(), None);
     }
 
     #[test]
@@ -72,42 +220,36 @@ mod tests {
 
         assert_eq!(item.name, new_name);
     }
-}
 
-mod my_date_format {
-    use chrono::{DateTime, TimeZone, Utc};
-    use serde::{self, Deserialize, Deserializer, Serializer};
-
-    const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
-
-    // The signature of a serialize_with function must follow the pattern:
-    //
-    //    fn serialize<S>(&T, S) -> Result<S::Ok, S::Error>
-    //    where
-    //        S: Serializer
-    //
-    // although it may also be generic over the input types T.
-    pub fn serialize<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let s = format!("{}", date.format(FORMAT));
-        serializer.serialize_str(&s)
-    }
-
-    // The signature of a deserialize_with function must follow the pattern:
-    //
-    //    fn deserialize<'de, D>(D) -> Result<T, D::Error>
-    //    where
-    //        D: Deserializer<'de>
-    //
-    // although it may also be generic over the output types T.
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let s = String::deserialize(deserializer)?;
-        Utc.datetime_from_str(&s, FORMAT)
-            .map_err(serde::de::Error::custom)
+    #[test]
+    fn it_formats_row() {
+        let today = NaiveDate::from_ymd(2024, 5, 1);
+        let mut item = TodoItem::new("test task");
+        assert_eq!(item.to_row_string(today), "[ ] - test task");
+
+        item.set_due(Some(DueDate {
+            date: today,
+            time: None,
+        }));
+        assert_eq!(item.to_row_string(today), "[ ] - test task  @today");
+
+        item.set_priority(Priority::High);
+        assert_eq!(item.to_row_string(today), "[ ] - !!! test task  @today");
+
+        item.set_tags(
+            vec!["work".to_string(), "home".to_string()]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(
+            item.to_row_string(today),
+            "[ ] - !!! test task #home #work  @today"
+        );
+
+        item.set_recurrence(Some(Recurrence::Daily));
+        assert_eq!(
+            item.to_row_string(today),
+            "[ ] - !!! test task #home #work  @today *daily"
+        );
     }
 }
@@ -1,19 +1,339 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use self_update::version::bump_is_greater;
+use self_update::{Download, Extract, Move, TempDir};
+
+use crate::config::UpdateSourceConfig;
+
 const REPO_OWNER: &str = "GlebIrovich";
 const REPO_NAME: &str = "rudo";
 const EXE_NAME: &str = "rudo";
 
 pub const CURRENT_APP_VERSION: &str = "0.2.3";
 
-pub fn update() -> Result<String, Box<dyn ::std::error::Error>> {
-    let status = self_update::backends::github::Update::configure()
-        .repo_owner(REPO_OWNER)
-        .repo_name(REPO_NAME)
-        .bin_name(EXE_NAME)
-        .show_download_progress(true)
-        .current_version(CURRENT_APP_VERSION)
-        .build()?
-        .update()?;
-
-    let version = status.version().to_string();
-    Ok(version)
+pub type UpdateResult<T> = Result<T, Box<dyn Error>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Release {
+    pub version: String,
+    // Where the source finds the archive: an url or a path
+    pub location: String,
+}
+
+/// Place where release archives are published.
+///
+/// Archives are named `rudo-<version>-<target>.tar.gz` and contain the `rudo` binary,
+/// the same way the release workflow publishes them.
+pub trait UpdateSource {
+    /// Returns the latest release built for the `target`.
+    fn get_latest_release(&self, target: &str) -> UpdateResult<Option<Release>>;
+
+    fn download(&self, release: &Release, destination: &mut dyn Write) -> UpdateResult<()>;
+}
+
+pub struct GithubSource {
+    pub repo_owner: String,
+    pub repo_name: String,
+}
+
+impl UpdateSource for GithubSource {
+    fn get_latest_release(&self, target: &str) -> UpdateResult<Option<Release>> {
+        let releases = self_update::backends::github::ReleaseList::configure()
+            .repo_owner(&self.repo_owner)
+            .repo_name(&self.repo_name)
+            .with_target(target)
+            .build()?
+            .fetch()?;
+
+        Ok(releases.first().and_then(|release| {
+            release.asset_for(target).map(|asset| Release {
+                version: release.version.clone(),
+                location: asset.download_url,
+            })
+        }))
+    }
+
+    fn download(&self, release: &Release, destination: &mut dyn Write) -> UpdateResult<()> {
+        Download::from_url(&release.location)
+            .show_progress(true)
+            .set_header(reqwest::header::ACCEPT, "application/octet-stream".parse()?)
+            .download_to(destination)?;
+
+        Ok(())
+    }
+}
+
+/// Static file server, e.g. an internal mirror. `<base_url>/latest` contains the latest version,
+/// archives are stored next to it.
+pub struct HttpSource {
+    pub base_url: String,
+}
+
+impl UpdateSource for HttpSource {
+    fn get_latest_release(&self, target: &str) -> UpdateResult<Option<Release>> {
+        let base_url = self.base_url.trim_end_matches('/');
+        let mut latest = vec![];
+        Download::from_url(&format!("{}/latest", base_url)).download_to(&mut latest)?;
+
+        let version = String::from_utf8(latest)?.trim().to_string();
+        if version.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Release {
+            location: format!("{}/{}", base_url, get_archive_name(&version, target)),
+            version,
+        }))
+    }
+
+    fn download(&self, release: &Release, destination: &mut dyn Write) -> UpdateResult<()> {
+        Download::from_url(&release.location)
+            .show_progress(true)
+            .download_to(destination)?;
+
+        Ok(())
+    }
+}
+
+/// Directory with release archives, e.g. a mounted share or a test fixture.
+pub struct LocalSource {
+    pub directory: PathBuf,
+}
+
+impl UpdateSource for LocalSource {
+    fn get_latest_release(&self, target: &str) -> UpdateResult<Option<Release>> {
+        let mut latest: Option<Release> = None;
+
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            let version = match path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| parse_archive_name(name, target))
+            {
+                Some(version) => version,
+                None => continue,
+            };
+
+            let is_newer = match &latest {
+                Some(release) => bump_is_greater(&release.version, &version)?,
+                None => true,
+            };
+            if is_newer {
+                latest = Some(Release {
+                    version,
+                    location: path.to_string_lossy().to_string(),
+                });
+            }
+        }
+
+        Ok(latest)
+    }
+
+    fn download(&self, release: &Release, destination: &mut dyn Write) -> UpdateResult<()> {
+        io::copy(&mut File::open(&release.location)?, destination)?;
+
+        Ok(())
+    }
+}
+
+pub fn get_update_source(config: &UpdateSourceConfig) -> Box<dyn UpdateSource + Send> {
+    match config {
+        UpdateSourceConfig::Github => Box::new(GithubSource {
+            repo_owner: REPO_OWNER.to_string(),
+            repo_name: REPO_NAME.to_string(),
+        }),
+        UpdateSourceConfig::Http(base_url) => Box::new(HttpSource {
+            base_url: base_url.clone(),
+        }),
+        UpdateSourceConfig::Local(directory) => Box::new(LocalSource {
+            directory: directory.clone(),
+        }),
+    }
+}
+
+pub struct Updater {
+    pub source: Box<dyn UpdateSource + Send>,
+    pub current_version: String,
+    pub target: String,
+    // Binary which gets replaced by the new release
+    pub install_path: PathBuf,
+}
+
+impl Updater {
+    pub fn new(source: Box<dyn UpdateSource + Send>) -> UpdateResult<Self> {
+        Ok(Updater {
+            source,
+            current_version: CURRENT_APP_VERSION.to_string(),
+            target: self_update::get_target().to_string(),
+            install_path: std::env::current_exe()?,
+        })
+    }
+
+    /// Returns the latest release if it is newer than the current version.
+    pub fn get_newer_release(&self) -> UpdateResult<Option<Release>> {
+        match self.source.get_latest_release(&self.target)? {
+            Some(release) if bump_is_greater(&self.current_version, &release.version)? => {
+                Ok(Some(release))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Downloads the release and replaces the installed binary with it.
+    pub fn install(&self, release: &Release) -> UpdateResult<()> {
+        // Temporary files live next to the binary, so it can be replaced by a rename
+        let install_dir = self
+            .install_path
+            .parent()
+            .ok_or("Install path has no parent directory")?;
+        let tmp_dir = TempDir::new_in(install_dir)?;
+
+        let archive_path = tmp_dir
+            .path()
+            .join(get_archive_name(&release.version, &self.target));
+        let mut archive = File::create(&archive_path)?;
+        self.source.download(release, &mut archive)?;
+        archive.sync_all()?;
+
+        Extract::from_source(&archive_path).extract_file(tmp_dir.path(), EXE_NAME)?;
+        let new_exe = tmp_dir.path().join(EXE_NAME);
+        set_executable(&new_exe)?;
+
+        Move::from_source(&new_exe)
+            .replace_using_temp(&tmp_dir.path().join("replaced_bin"))
+            .to_dest(&self.install_path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn get_archive_name(version: &str, target: &str) -> String {
+    format!("{}-{}-{}.tar.gz", EXE_NAME, version, target)
+}
+
+// Returns version from an archive name, tags with `v` prefix are accepted as well
+fn parse_archive_name(name: &str, target: &str) -> Option<String> {
+    let version = name
+        .strip_prefix(&format!("{}-", EXE_NAME))?
+        .strip_suffix(&format!("-{}.tar.gz", target))?;
+    let version = version.strip_prefix('v').unwrap_or(version);
+
+    match bump_is_greater(version, version) {
+        Ok(_) => Some(version.to_string()),
+        Err(_) => None,
+    }
+}
+
+/// Checks for a new release in background. The receiver gets the new version if there is one,
+/// any error during the check is ignored, so being offline never affects the app.
+pub fn spawn_update_check(source: Box<dyn UpdateSource + Send>) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        if let Ok(Some(release)) = Updater::new(source).and_then(|u| u.get_newer_release()) {
+            let _ = sender.send(release.version);
+        }
+    });
+
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use tempfile::tempdir;
+
+    static TARGET: &str = "test-target";
+
+    fn create_release_archive(directory: &Path, version: &str, content: &str) {
+        let archive = File::create(directory.join(get_archive_name(version, TARGET))).unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(archive, Compression::default()));
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, EXE_NAME, content.as_bytes())
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn create_updater(releases_dir: &Path, install_path: &Path) -> Updater {
+        Updater {
+            source: Box::new(LocalSource {
+                directory: releases_dir.to_path_buf(),
+            }),
+            current_version: "0.2.3".to_string(),
+            target: TARGET.to_string(),
+            install_path: install_path.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn it_parses_archive_names() {
+        assert_eq!(
+            parse_archive_name("rudo-v0.3.0-test-target.tar.gz", TARGET),
+            Some("0.3.0".to_string())
+        );
+        assert_eq!(
+            parse_archive_name("rudo-0.3.0-other-target.tar.gz", TARGET),
+            None
+        );
+        assert_eq!(
+            parse_archive_name("rudo-latest-test-target.tar.gz", TARGET),
+            None
+        );
+    }
+
+    #[test]
+    fn it_updates_from_local_directory() {
+        let releases_dir = tempdir().unwrap();
+        let install_dir = tempdir().unwrap();
+        let install_path = install_dir.path().join(EXE_NAME);
+        fs::write(&install_path, "old binary").unwrap();
+
+        create_release_archive(releases_dir.path(), "0.2.3", "same binary");
+        create_release_archive(releases_dir.path(), "0.10.0", "newest binary");
+        create_release_archive(releases_dir.path(), "0.9.0", "newer binary");
+
+        let updater = create_updater(releases_dir.path(), &install_path);
+        let release = updater.get_newer_release().unwrap().unwrap();
+        assert_eq!(release.version, "0.10.0");
+
+        updater.install(&release).unwrap();
+        assert_eq!(fs::read_to_string(&install_path).unwrap(), "newest binary");
+        // Only the binary is left, temporary files are cleaned up
+        assert_eq!(fs::read_dir(install_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn it_does_not_offer_older_releases() {
+        let releases_dir = tempdir().unwrap();
+        create_release_archive(releases_dir.path(), "0.2.3", "same binary");
+        create_release_archive(releases_dir.path(), "0.1.0", "older binary");
+
+        let updater = create_updater(releases_dir.path(), Path::new("/tmp/rudo"));
+        assert_eq!(updater.get_newer_release().unwrap(), None);
+    }
 }
@@ -1,5 +1,22 @@
+use std::io::{self, Write};
+
 use tui::widgets::ListState;
 
+/// Asks a yes/no question on the terminal. Empty answer means `default`.
+pub fn confirm(question: &str, default: bool) -> io::Result<bool> {
+    print!("{} ", question);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(match answer.trim().to_lowercase().as_str() {
+        "" => default,
+        "y" | "yes" => true,
+        _ => false,
+    })
+}
+
 pub struct StatefulList<T> {
     pub state: ListState,
     pub items: Vec<T>,
@@ -17,52 +34,45 @@ where
     }
 
     pub fn next(&mut self) {
-        let number_of_items = self.items.len();
+        self.select_next(self.items.len());
+    }
+
+    pub fn previous(&mut self) {
+        self.select_previous(self.items.len());
+    }
+
+    /// Selects the next row of a list showing `number_of_items` rows, e.g. only some of the items.
+    pub fn select_next(&mut self, number_of_items: usize) {
         let item = match self.state.selected() {
-            Some(i) => {
-                if i >= number_of_items - 1 {
-                    Some(0)
-                } else {
-                    Some(i + 1)
-                }
-            }
-            None => {
-                if number_of_items > 0 {
-                    Some(0)
-                } else {
-                    None
-                }
-            }
+            _ if number_of_items == 0 => None,
+            Some(i) if i + 1 < number_of_items => Some(i + 1),
+            _ => Some(0),
         };
         self.state.select(item);
     }
 
-    pub fn previous(&mut self) {
-        let number_of_items = self.items.len();
+    pub fn select_previous(&mut self, number_of_items: usize) {
+        let item = match self.state.selected() {
+            _ if number_of_items == 0 => None,
+            Some(i) if i > 0 && i <= number_of_items => Some(i - 1),
+            _ => Some(number_of_items - 1),
+        };
+        self.state.select(item);
+    }
+
+    /// Moves the selection by `offset` rows without wrapping around, starting from the first row
+    /// if nothing is selected.
+    pub fn select_offset(&mut self, offset: isize, number_of_items: usize) {
         let item = match self.state.selected() {
-            Some(i) => {
-                if i == 0 {
-                    Some(number_of_items - 1)
-                } else {
-                    Some(i - 1)
-                }
-            }
-            None => {
-                if number_of_items > 0 {
-                    Some(number_of_items - 1)
-                } else {
-                    None
-                }
-            }
+            _ if number_of_items == 0 => None,
+            Some(i) => Some((i as isize + offset).clamp(0, number_of_items as isize - 1) as usize),
+            None => Some(0),
         };
         self.state.select(item);
     }
 
     pub fn get_selected_item(&self) -> Option<T> {
-        match self.state.selected() {
-            Some(index) => Some(self.items[index].clone()),
-            _ => None,
-        }
+        self.state.selected().map(|index| self.items[index].clone())
     }
 }
 
@@ -81,5 +91,20 @@ mod tests {
         assert_eq!(list.get_selected_item(), Some("b"));
         list.previous();
         assert_eq!(list.get_selected_item(), Some("a"));
+        list.previous();
+        assert_eq!(list.get_selected_item(), Some("c"));
+
+        // Only the first two items are shown
+        list.select_next(2);
+        assert_eq!(list.get_selected_item(), Some("a"));
+        list.select_next(0);
+        assert_eq!(list.state.selected(), None);
+
+        list.select_offset(2, 3);
+        assert_eq!(list.get_selected_item(), Some("a"));
+        list.select_offset(5, 3);
+        assert_eq!(list.get_selected_item(), Some("c"));
+        list.select_offset(-2, 3);
+        assert_eq!(list.get_selected_item(), Some("a"));
     }
 }